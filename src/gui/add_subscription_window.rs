@@ -12,14 +12,110 @@ use lapin::{
 };
 use uuid::Uuid;
 
-use crate::rabbit::Binding;
+use crate::rabbit::{matching::topic_matches, Binding, ExchangeType};
 
 use super::enums::ModalResult;
+use super::model::Model;
+use super::state::ConnectionStatus;
+
+/// At most this many routing keys are listed by the topic pattern tester.
+const PREVIEW_LIMIT: usize = 20;
+
+/// The routing keys captured from an exchange, split by whether a topic pattern matches
+/// them, along with the number of messages with each key.
+pub(crate) struct TopicPreview {
+    pattern: String,
+    exchange: String,
+    matching: Vec<(String, usize)>,
+    other: Vec<(String, usize)>,
+}
+
+impl TopicPreview {
+    fn new(pattern: &str, exchange: &str, model: &Model) -> Self {
+        let (matching, other) = model
+            .routing_key_counts(exchange)
+            .into_iter()
+            .map(|(key, count)| (key.to_string(), count))
+            .partition(|(key, _)| topic_matches(pattern, key));
+        Self {
+            pattern: pattern.into(),
+            exchange: exchange.into(),
+            matching,
+            other,
+        }
+    }
+}
+
+/// Which routing keys of the messages captured from `exchange` the topic `pattern` would
+/// match, so that it can be got right before binding. The keys are only gone through again
+/// when the pattern or exchange changes.
+fn topic_preview(
+    ui: &mut egui::Ui,
+    preview: &mut Option<TopicPreview>,
+    pattern: &str,
+    exchange: &str,
+    model: &Model,
+) {
+    let preview = match preview {
+        Some(p) if p.pattern == pattern && p.exchange == exchange => p,
+        _ => preview.insert(TopicPreview::new(pattern, exchange, model)),
+    };
+    let TopicPreview {
+        matching, other, ..
+    } = preview;
+    let key_count = matching.len() + other.len();
+    let matched_messages: usize = matching.iter().map(|(_, count)| *count).sum();
+
+    egui::CollapsingHeader::new(format!(
+        "Matches {} of {} captured routing keys ({} messages)",
+        matching.len(),
+        key_count,
+        matched_messages
+    ))
+    .id_salt("topic_preview")
+    .show(ui, |ui| {
+        if key_count == 0 {
+            ui.label(
+                RichText::new(format!("No messages from '{}' captured", exchange))
+                    .color(Color32::GRAY),
+            );
+            return;
+        }
+        Grid::new("topic_preview_keys")
+            .num_columns(2)
+            .striped(true)
+            .show(ui, |ui| {
+                // Matches first, then (greyed out) those which would be missed.
+                let listed = matching
+                    .iter()
+                    .map(|entry| (entry, true))
+                    .chain(other.iter().map(|entry| (entry, false)));
+                for ((key, count), matches) in listed.take(PREVIEW_LIMIT) {
+                    let colour = if matches {
+                        Color32::GREEN
+                    } else {
+                        Color32::GRAY
+                    };
+                    let mark = if matches { icon::CHECK } else { icon::X };
+                    ui.label(RichText::new(format!("{} {}", mark, key)).color(colour));
+                    ui.label(RichText::new(count.to_string()).color(colour));
+                    ui.end_row();
+                }
+            });
+        if key_count > PREVIEW_LIMIT {
+            ui.label(
+                RichText::new(format!("and {} more", key_count - PREVIEW_LIMIT))
+                    .color(Color32::GRAY),
+            );
+        }
+    });
+}
 
 #[derive(PartialEq, Clone)]
 enum SubscriptionArgumentType {
     Boolean,
     LongString,
+    ShortString,
     LongLongInt,
     LongInt,
     LongUInt,
@@ -42,6 +138,7 @@ impl Display for SubscriptionArgumentType {
                 Self::LongUInt => "32 bit unsigned int",
                 Self::DecimalValue => "Decimal",
                 Self::LongString => "String",
+                Self::ShortString => "Short string",
                 Self::LongLongInt => "64 bit int",
                 Self::ShortInt => "16 bit int",
                 Self::ShortUInt => "16 bit unsigned int",
@@ -54,13 +151,99 @@ impl Display for SubscriptionArgumentType {
     }
 }
 
+/// How a headers exchange binding's arguments are matched against message headers,
+/// i.e. the value of its `x-match` argument.
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum HeaderMatch {
+    All,
+    Any,
+    AllWithX,
+    AnyWithX,
+}
+
+impl HeaderMatch {
+    const VARIANTS: [Self; 4] = [Self::All, Self::Any, Self::AllWithX, Self::AnyWithX];
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::All => "all",
+            Self::Any => "any",
+            Self::AllWithX => "all-with-x",
+            Self::AnyWithX => "any-with-x",
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            Self::All => "Every argument must match a header, ignoring those beginning x-",
+            Self::Any => "At least one argument must match a header, ignoring those beginning x-",
+            Self::AllWithX => "Every argument must match a header, including those beginning x-",
+            Self::AnyWithX => {
+                "At least one argument must match a header, including those beginning x-"
+            }
+        }
+    }
+}
+
 pub(crate) struct SubscriptionParams {
     pub exchange: String,
+    pub exchange_type: ExchangeType,
     pub routing_key: String,
+    /// Only for headers exchanges.
+    pub header_match: HeaderMatch,
     pub arguments: Vec<RawSubscriptionArgument>,
+    /// Whether `x-match` is sent as a short string, as it may have been in a binding being
+    /// edited, rather than a long one.
+    pub short_x_match: bool,
+    /// The id of the binding being edited, if not adding a new one.
+    pub replacing: Option<Uuid>,
+    /// Why the dialogue has been (re)opened, if other than at the user's request.
+    pub notice: Option<String>,
+    /// Whether the edit has been sent, the dialogue staying open until the broker confirms it.
+    pub rebinding: bool,
+    preview: Option<TopicPreview>,
 }
 
 impl SubscriptionParams {
+    /// Parameters for editing an existing binding.
+    pub(crate) fn from_binding(binding: &Binding) -> Result<Self, String> {
+        let x_match = binding.arguments.inner().get("x-match");
+        let header_match = match x_match {
+            None => None,
+            Some(AMQPValue::LongString(s)) => Some(s.to_string()),
+            Some(AMQPValue::ShortString(s)) => Some(s.to_string()),
+            Some(_) => return Err("x-match is not a string".into()),
+        };
+        let header_match = header_match
+            .map(|x_match| {
+                HeaderMatch::VARIANTS
+                    .into_iter()
+                    .find(|m| m.as_str() == x_match)
+                    .ok_or_else(|| format!("Unknown x-match '{}'", x_match))
+            })
+            .transpose()?;
+        let arguments = binding
+            .arguments
+            .inner()
+            .iter()
+            .filter(|(name, _)| name.as_str() != "x-match")
+            .map(|(name, value)| RawSubscriptionArgument::from_value(name.as_str(), value))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            exchange: binding.exchange.clone(),
+            exchange_type: binding.exchange_type,
+            routing_key: binding.routing_key.clone(),
+            header_match: header_match.unwrap_or(HeaderMatch::All),
+            arguments,
+            short_x_match: matches!(x_match, Some(AMQPValue::ShortString(_))),
+            replacing: Some(binding.id),
+            notice: None,
+            rebinding: false,
+            preview: None,
+        })
+    }
+
     // TODO; just use this to generate the dialogue validation error
     pub(crate) fn as_binding(&self) -> Result<Binding, String> {
         if self.exchange.is_empty() {
@@ -69,13 +252,38 @@ impl SubscriptionParams {
 
         let mut args = BTreeMap::<ShortString, AMQPValue>::new();
         for a in &self.arguments {
+            if a.name == "x-match" {
+                return Err(match self.exchange_type {
+                    ExchangeType::Headers => "Set x-match with the header match selector".into(),
+                    _ => "x-match only applies to headers exchanges".into(),
+                });
+            }
             args.insert(ShortString::from(a.name.clone()), a.parse_value()?);
         }
 
+        // Routing keys are ignored by fanout and headers exchanges, so leave them empty.
+        let routing_key = match self.exchange_type {
+            ExchangeType::Direct | ExchangeType::Topic => self.routing_key.clone(),
+            ExchangeType::Fanout => String::default(),
+            ExchangeType::Headers => {
+                let x_match = self.header_match.as_str();
+                args.insert(
+                    ShortString::from("x-match"),
+                    if self.short_x_match {
+                        AMQPValue::ShortString(x_match.into())
+                    } else {
+                        AMQPValue::LongString(x_match.into())
+                    },
+                );
+                String::default()
+            }
+        };
+
         Ok(Binding {
             id: Uuid::new_v4(),
             exchange: self.exchange.clone(),
-            routing_key: self.routing_key.clone(),
+            exchange_type: self.exchange_type,
+            routing_key,
             arguments: args.into(),
         })
     }
@@ -98,6 +306,41 @@ impl Default for RawSubscriptionArgument {
 }
 
 impl RawSubscriptionArgument {
+    /// The inverse of `parse_value`, for those types which can be entered.
+    fn from_value(name: &str, value: &AMQPValue) -> Result<Self, String> {
+        let (t, value) = match value {
+            AMQPValue::Boolean(b) => (SubscriptionArgumentType::Boolean, b.to_string()),
+            AMQPValue::LongInt(i) => (SubscriptionArgumentType::LongInt, i.to_string()),
+            AMQPValue::LongUInt(u) => (SubscriptionArgumentType::LongUInt, u.to_string()),
+            AMQPValue::DecimalValue(d) => (
+                SubscriptionArgumentType::DecimalValue,
+                serde_json::to_string(d).map_err(|e| e.to_string())?,
+            ),
+            AMQPValue::LongString(s) => (SubscriptionArgumentType::LongString, s.to_string()),
+            AMQPValue::ShortString(s) => (SubscriptionArgumentType::ShortString, s.to_string()),
+            AMQPValue::LongLongInt(i) => (SubscriptionArgumentType::LongLongInt, i.to_string()),
+            AMQPValue::ShortInt(i) => (SubscriptionArgumentType::ShortInt, i.to_string()),
+            AMQPValue::ShortUInt(u) => (SubscriptionArgumentType::ShortUInt, u.to_string()),
+            AMQPValue::ShortShortInt(i) => (SubscriptionArgumentType::ShortShortInt, i.to_string()),
+            AMQPValue::ShortShortUInt(u) => {
+                (SubscriptionArgumentType::ShortShortUInt, u.to_string())
+            }
+            AMQPValue::Float(f) => (SubscriptionArgumentType::Float, f.to_string()),
+            AMQPValue::Double(d) => (SubscriptionArgumentType::Double, d.to_string()),
+            _ => {
+                return Err(format!(
+                    "Argument '{}' is of a type which cannot be edited",
+                    name
+                ))
+            }
+        };
+        Ok(Self {
+            t,
+            value,
+            name: name.into(),
+        })
+    }
+
     pub fn is_valid(&self) -> bool {
         self.is_name_valid() && self.is_value_valid()
     }
@@ -141,6 +384,15 @@ impl RawSubscriptionArgument {
             SubscriptionArgumentType::LongString => {
                 AMQPValue::LongString(self.value.clone().into())
             }
+            SubscriptionArgumentType::ShortString => {
+                if self.value.len() > u8::MAX as usize {
+                    return Err(format!(
+                        "Value of field '{}' is too long for a short string",
+                        self.name
+                    ));
+                }
+                AMQPValue::ShortString(self.value.clone().into())
+            }
             SubscriptionArgumentType::LongLongInt => {
                 AMQPValue::LongLongInt(self.value.parse().map_err(|_| parse_error(self))?)
             }
@@ -174,14 +426,22 @@ impl Default for SubscriptionParams {
     fn default() -> Self {
         Self {
             exchange: String::default(),
+            exchange_type: ExchangeType::default(),
             routing_key: String::default(),
+            header_match: HeaderMatch::All,
             arguments: Vec::<RawSubscriptionArgument>::default(),
+            short_x_match: false,
+            replacing: None,
+            notice: None,
+            rebinding: false,
+            preview: None,
         }
     }
 }
 
 impl super::App {
     pub(crate) fn add_subscription_window(&mut self, ctx: &Context) {
+        let connected = self.gui_state.connection == ConnectionStatus::Connected;
         if let Some(params) = &mut self.gui_state.add_subscription_parameters {
             let mut delete_index = None;
 
@@ -200,7 +460,18 @@ impl super::App {
                     error = e;
                 }
             }
-            Window::new("Add subscription")
+            // An edit is made on the live connection, so cannot be made without one.
+            let error = match (params.rebinding, params.replacing) {
+                _ if !error.is_empty() => error,
+                (true, _) => "Waiting for the broker to make the change".to_string(),
+                (false, Some(_)) if !connected => "Connect to edit the subscription".to_string(),
+                _ => error,
+            };
+            let title = match params.replacing {
+                Some(_) => "Edit subscription",
+                None => "Add subscription",
+            };
+            Window::new(title)
                 .movable(true)
                 .resizable(true)
                 .collapsible(false)
@@ -210,6 +481,9 @@ impl super::App {
                         size: 16.0,
                         family: FontFamily::Proportional,
                     });
+                    if let Some(notice) = &params.notice {
+                        ui.label(RichText::new(notice).color(Color32::YELLOW));
+                    }
                     Grid::new("subscription")
                         .num_columns(2)
                         .min_col_width(100.0)
@@ -230,13 +504,66 @@ impl super::App {
                             ui.end_row();
 
                             ui.with_layout(egui::Layout::right_to_left(Align::Center), |ui| {
-                                ui.label("Routing key");
-                            });
-                            ui.add_sized([ui.available_width(), 16.0], {
-                                egui::TextEdit::singleline(&mut params.routing_key)
+                                ui.label("Exchange type");
                             });
+                            egui::ComboBox::from_id_salt("exchange_type")
+                                .selected_text(params.exchange_type.to_string())
+                                .show_ui(ui, |ui| {
+                                    for exchange_type in ExchangeType::ALL {
+                                        ui.selectable_value(
+                                            &mut params.exchange_type,
+                                            exchange_type,
+                                            exchange_type.to_string(),
+                                        );
+                                    }
+                                });
                             ui.end_row();
+
+                            match params.exchange_type {
+                                ExchangeType::Direct | ExchangeType::Topic => {
+                                    ui.with_layout(
+                                        egui::Layout::right_to_left(Align::Center),
+                                        |ui| {
+                                            ui.label("Routing key");
+                                        },
+                                    );
+                                    ui.add_sized([ui.available_width(), 16.0], {
+                                        egui::TextEdit::singleline(&mut params.routing_key)
+                                    });
+                                    ui.end_row();
+                                }
+                                ExchangeType::Fanout => (),
+                                ExchangeType::Headers => {
+                                    ui.with_layout(
+                                        egui::Layout::right_to_left(Align::Center),
+                                        |ui| {
+                                            ui.label("Match");
+                                        },
+                                    );
+                                    ui.horizontal(|ui| {
+                                        for header_match in HeaderMatch::VARIANTS {
+                                            ui.radio_value(
+                                                &mut params.header_match,
+                                                header_match,
+                                                header_match.as_str(),
+                                            )
+                                            .on_hover_text(header_match.description());
+                                        }
+                                    });
+                                    ui.end_row();
+                                }
+                            }
                         });
+                    if params.exchange_type == ExchangeType::Topic && !params.routing_key.is_empty()
+                    {
+                        topic_preview(
+                            ui,
+                            &mut params.preview,
+                            &params.routing_key,
+                            &params.exchange,
+                            &self.gui_data,
+                        );
+                    }
                     ui.add(egui::Separator::default().horizontal());
 
                     // Table of arguments
@@ -309,6 +636,11 @@ impl super::App {
                                                     SubscriptionArgumentType::LongString,
                                                     "string",
                                                 );
+                                                ui.selectable_value(
+                                                    &mut arg.t,
+                                                    SubscriptionArgumentType::ShortString,
+                                                    "short string",
+                                                );
                                                 ui.selectable_value(
                                                     &mut arg.t,
                                                     SubscriptionArgumentType::LongLongInt,
@@ -393,7 +725,18 @@ impl super::App {
                                         .on_disabled_hover_text(error)
                                         .clicked()
                                     {
-                                        let _ = self.connection_manager.bind(binding.unwrap());
+                                        let mut binding = binding.unwrap();
+                                        match params.replacing {
+                                            // Closed once the broker has made the change;
+                                            // see `rebound` and `rebind_failed`.
+                                            Some(id) => {
+                                                binding.id = id;
+                                                self.connection_manager.rebind(binding);
+                                                params.rebinding = true;
+                                                params.notice = None;
+                                            }
+                                            None => self.connection_manager.bind(binding),
+                                        }
                                     }
                                 });
                             });
@@ -421,3 +764,39 @@ impl super::App {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn editing_without_changes_keeps_the_arguments() {
+        let mut arguments = FieldTable::default();
+        arguments.insert("x-match".into(), AMQPValue::ShortString("any".into()));
+        arguments.insert("tenant".into(), AMQPValue::ShortString("acme".into()));
+        arguments.insert("region".into(), AMQPValue::LongString("eu".into()));
+        arguments.insert("priority".into(), AMQPValue::ShortShortUInt(3));
+        let binding = Binding {
+            id: Uuid::new_v4(),
+            exchange: "events".into(),
+            exchange_type: ExchangeType::Headers,
+            routing_key: String::default(),
+            arguments,
+        };
+        let params = SubscriptionParams::from_binding(&binding).unwrap();
+        assert!(params.exchange_type == ExchangeType::Headers);
+        let edited = params.as_binding().unwrap();
+        assert_eq!(edited.arguments, binding.arguments);
+    }
+
+    #[test]
+    fn short_strings_are_limited_in_length() {
+        let argument = |value: String| RawSubscriptionArgument {
+            t: SubscriptionArgumentType::ShortString,
+            value,
+            name: "tenant".into(),
+        };
+        assert!(argument("a".repeat(255)).parse_value().is_ok());
+        assert!(argument("a".repeat(256)).parse_value().is_err());
+    }
+}
@@ -0,0 +1,448 @@
+use std::collections::VecDeque;
+
+use eframe::egui::{
+    self, Align, Button, Color32, Context, FontFamily, FontId, Layout, RichText, TextEdit, Window,
+};
+use egui_extras::{Column, TableBuilder};
+use egui_phosphor::regular as icon;
+
+use super::model::ModelItem;
+use super::query::Query;
+
+/// The most recent alerts are kept for review; older ones are discarded.
+const FIRED_LIMIT: usize = 100;
+
+/// Fire when more than `threshold` messages matching the filter arrive within `window_secs`.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct AlertRule {
+    pub name: String,
+    pub enabled: bool,
+    pub filter_string: String,
+    /// Interpret the filter string as a structured query rather than a regex.
+    pub structured: bool,
+    pub threshold: usize,
+    pub window_secs: u32,
+    /// Shell command to run when the rule fires, if not empty.
+    pub command: String,
+}
+
+impl Default for AlertRule {
+    fn default() -> Self {
+        Self {
+            name: String::default(),
+            enabled: true,
+            filter_string: String::default(),
+            structured: false,
+            threshold: 0,
+            window_secs: 60,
+            command: String::default(),
+        }
+    }
+}
+
+enum Matcher {
+    Regex(regex::Regex),
+    Query(Query),
+}
+
+impl Matcher {
+    /// Regexes are sought in both the headers and the body.
+    fn matches(&self, item: &ModelItem) -> bool {
+        match self {
+            Matcher::Regex(regex) => regex.is_match(&item.headers) || regex.is_match(&item.body),
+            Matcher::Query(query) => query.matches(item),
+        }
+    }
+}
+
+/// Compiled filter and recent matches of a rule.
+#[derive(Default)]
+struct RuleState {
+    /// The filter string compiled, and whether as a structured query.
+    source: (String, bool),
+    matcher: Option<Matcher>,
+    error: Option<String>,
+    hits: VecDeque<chrono::DateTime<chrono::Local>>,
+}
+
+impl RuleState {
+    fn new(rule: &AlertRule) -> Self {
+        let matcher = if rule.filter_string.is_empty() {
+            Ok(None)
+        } else if rule.structured {
+            Query::parse(&rule.filter_string).map(|q| Some(Matcher::Query(q)))
+        } else {
+            regex::Regex::new(&rule.filter_string)
+                .map(|r| Some(Matcher::Regex(r)))
+                .map_err(|e| e.to_string())
+        };
+        let source = (rule.filter_string.clone(), rule.structured);
+        match matcher {
+            Ok(matcher) => Self {
+                source,
+                matcher,
+                ..Default::default()
+            },
+            Err(error) => Self {
+                source,
+                error: Some(error),
+                ..Default::default()
+            },
+        }
+    }
+
+    fn compiled_for(&self, rule: &AlertRule) -> bool {
+        self.source.0 == rule.filter_string && self.source.1 == rule.structured
+    }
+}
+
+pub struct FiredAlert {
+    pub rule: String,
+    pub at: chrono::DateTime<chrono::Local>,
+    pub count: usize,
+}
+
+#[derive(Default)]
+pub struct Alerts {
+    pub rules: Vec<AlertRule>,
+    /// Parallel to `rules`.
+    states: Vec<RuleState>,
+    /// Most recent last.
+    pub fired: VecDeque<FiredAlert>,
+    /// Alerts fired since the user last looked.
+    pub unacknowledged: usize,
+    /// An alert has fired since the user's attention was last requested.
+    pub attention_needed: bool,
+}
+
+impl Alerts {
+    pub fn new(rules: Vec<AlertRule>) -> Self {
+        let mut alerts = Self {
+            rules,
+            ..Default::default()
+        };
+        alerts.compile();
+        alerts
+    }
+
+    /// Bring the matchers up to date after rules have been added or edited. Only a rule
+    /// whose filter has changed is compiled again, which also resets its count.
+    pub fn compile(&mut self) {
+        self.states.truncate(self.rules.len());
+        for (index, rule) in self.rules.iter().enumerate() {
+            match self.states.get_mut(index) {
+                Some(state) if state.compiled_for(rule) => (),
+                Some(state) => *state = RuleState::new(rule),
+                None => self.states.push(RuleState::new(rule)),
+            }
+        }
+    }
+
+    /// Delete a rule, along with its count.
+    pub fn remove(&mut self, index: usize) {
+        self.rules.remove(index);
+        self.states.remove(index);
+    }
+
+    pub fn evaluate(&mut self, item: &ModelItem) {
+        for (rule, state) in self.rules.iter().zip(self.states.iter_mut()) {
+            let Some(matcher) = &state.matcher else {
+                continue;
+            };
+            if !rule.enabled || !matcher.matches(item) {
+                continue;
+            }
+            let oldest = item.received - chrono::TimeDelta::seconds(rule.window_secs as i64);
+            state.hits.push_back(item.received);
+            while state.hits.front().is_some_and(|t| *t < oldest) {
+                state.hits.pop_front();
+            }
+            if state.hits.len() > rule.threshold {
+                let count = state.hits.len();
+                // Start counting afresh, so that a sustained burst does not fire on every message.
+                state.hits.clear();
+                log::warn!("Alert '{}': {} messages", rule.name, count);
+                if !rule.command.is_empty() {
+                    run_command(&rule.command, &rule.name, count);
+                }
+                if self.fired.len() == FIRED_LIMIT {
+                    self.fired.pop_front();
+                }
+                self.fired.push_back(FiredAlert {
+                    rule: rule.name.clone(),
+                    at: item.received,
+                    count,
+                });
+                self.unacknowledged += 1;
+                self.attention_needed = true;
+            }
+        }
+    }
+}
+
+/// Run an alert's command via the shell, without waiting for it to complete. The rule name
+/// and message count are passed in the environment.
+fn run_command(command: &str, rule: &str, count: usize) {
+    #[cfg(target_os = "windows")]
+    let mut shell = {
+        let mut shell = std::process::Command::new("cmd");
+        shell.args(["/C", command]);
+        shell
+    };
+    #[cfg(not(target_os = "windows"))]
+    let mut shell = {
+        let mut shell = std::process::Command::new("sh");
+        shell.args(["-c", command]);
+        shell
+    };
+    shell
+        .env("ELMER_ALERT", rule)
+        .env("ELMER_ALERT_COUNT", count.to_string());
+    match shell.spawn() {
+        Ok(mut child) => {
+            // Reap the child once it exits.
+            std::thread::spawn(move || child.wait());
+        }
+        Err(e) => log::error!("Failed to run alert command '{}': {}", command, e),
+    }
+}
+
+impl super::App {
+    /// Window for editing alert rules and reviewing those which have fired.
+    pub(crate) fn alerts_window(&mut self, ctx: &Context) {
+        if !self.gui_state.show_alerts {
+            return;
+        }
+        let alerts = &mut self.alerts;
+        alerts.unacknowledged = 0;
+        let mut changed = false;
+        let mut delete_index = None;
+
+        Window::new("Alerts")
+            .movable(true)
+            .resizable(true)
+            .collapsible(false)
+            .open(&mut self.gui_state.show_alerts)
+            .show(ctx, |ui| {
+                let style = ui.style_mut();
+                style.override_font_id = Some(FontId {
+                    size: 16.0,
+                    family: FontFamily::Proportional,
+                });
+                let available_height = ui.available_height();
+                TableBuilder::new(ui)
+                    .id_salt("alert_rules")
+                    .striped(false)
+                    .resizable(false)
+                    .cell_layout(Layout::left_to_right(Align::Center))
+                    .column(Column::auto())
+                    .column(Column::auto().at_least(100.0))
+                    .column(Column::remainder().at_least(160.0))
+                    .columns(Column::auto(), 3)
+                    .column(Column::auto().at_least(120.0))
+                    .column(Column::auto().at_least(32.0))
+                    .auto_shrink([false, true])
+                    .max_scroll_height(available_height / 2.0)
+                    .header(16.0, |mut header| {
+                        for heading in [
+                            "",
+                            "Name",
+                            "Filter",
+                            "Query",
+                            "More than",
+                            "Within",
+                            "Command",
+                        ] {
+                            header.col(|ui| {
+                                ui.label(heading);
+                            });
+                        }
+                        header.col(|ui| {
+                            if ui
+                                .add(
+                                    Button::new(RichText::new(icon::PLUS).color(Color32::GREEN))
+                                        .fill(Color32::TRANSPARENT),
+                                )
+                                .on_hover_text("Add rule")
+                                .clicked()
+                            {
+                                alerts.rules.push(AlertRule::default());
+                                changed = true;
+                            }
+                        });
+                    })
+                    .body(|mut body| {
+                        for (index, rule) in alerts.rules.iter_mut().enumerate() {
+                            let error = alerts.states.get(index).and_then(|s| s.error.clone());
+                            body.row(20.0, |mut row| {
+                                row.col(|ui| {
+                                    changed |= ui
+                                        .checkbox(&mut rule.enabled, "")
+                                        .on_hover_text("Enabled")
+                                        .changed();
+                                });
+                                row.col(|ui| {
+                                    changed |= ui
+                                        .add(
+                                            TextEdit::singleline(&mut rule.name)
+                                                .hint_text("Errors"),
+                                        )
+                                        .changed();
+                                });
+                                row.col(|ui| {
+                                    let mut edit = TextEdit::singleline(&mut rule.filter_string)
+                                        .hint_text("error.*true");
+                                    if error.is_some() {
+                                        edit = edit.text_color(Color32::RED);
+                                    }
+                                    let response = ui.add(edit);
+                                    changed |= response.changed();
+                                    if let Some(error) = error {
+                                        response.on_hover_text(error);
+                                    }
+                                });
+                                row.col(|ui| {
+                                    changed |= ui
+                                        .checkbox(&mut rule.structured, "")
+                                        .on_hover_text("Structured query rather than regex")
+                                        .changed();
+                                });
+                                row.col(|ui| {
+                                    changed |=
+                                        ui.add(egui::DragValue::new(&mut rule.threshold)).changed();
+                                });
+                                row.col(|ui| {
+                                    changed |= ui
+                                        .add(
+                                            egui::DragValue::new(&mut rule.window_secs)
+                                                .range(1..=86400)
+                                                .suffix(" s"),
+                                        )
+                                        .changed();
+                                });
+                                row.col(|ui| {
+                                    changed |= ui
+                                        .add(
+                                            TextEdit::singleline(&mut rule.command)
+                                                .hint_text("(none)"),
+                                        )
+                                        .changed();
+                                });
+                                row.col(|ui| {
+                                    if ui
+                                        .add(
+                                            Button::new(
+                                                RichText::new(icon::TRASH).color(Color32::RED),
+                                            )
+                                            .fill(Color32::TRANSPARENT),
+                                        )
+                                        .clicked()
+                                    {
+                                        delete_index = Some(index);
+                                    }
+                                });
+                            });
+                        }
+                    });
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    ui.label(RichText::new("Fired").strong());
+                    if ui.button("Clear").clicked() {
+                        alerts.fired.clear();
+                    }
+                });
+                egui::ScrollArea::vertical()
+                    .id_salt("fired_alerts")
+                    .auto_shrink([false, true])
+                    .show(ui, |ui| {
+                        for fired in alerts.fired.iter().rev() {
+                            ui.label(format!(
+                                "{}  {} ({} messages)",
+                                fired.at.format("%m/%d %H:%M:%S"),
+                                fired.rule,
+                                fired.count
+                            ));
+                        }
+                    });
+            });
+
+        if let Some(index) = delete_index {
+            self.alerts.remove(index);
+        }
+        if changed {
+            self.alerts.compile();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rabbit::DeliveryInfo;
+
+    fn alerts(threshold: usize) -> Alerts {
+        Alerts::new(vec![AlertRule {
+            name: "errors".into(),
+            filter_string: "error".into(),
+            threshold,
+            window_secs: 60,
+            ..Default::default()
+        }])
+    }
+
+    /// Deliver a message with the given body, `secs` seconds after `start`.
+    fn deliver(alerts: &mut Alerts, start: chrono::DateTime<chrono::Local>, secs: i64, body: &str) {
+        let mut item = ModelItem::new("{}".into(), body.into(), DeliveryInfo::default());
+        item.received = start + chrono::TimeDelta::seconds(secs);
+        alerts.evaluate(&item);
+    }
+
+    #[test]
+    fn fires_when_threshold_is_exceeded_within_window() {
+        let mut alerts = alerts(2);
+        let start = chrono::Local::now();
+        deliver(&mut alerts, start, 0, "error");
+        deliver(&mut alerts, start, 10, "fine");
+        deliver(&mut alerts, start, 20, "error");
+        assert!(alerts.fired.is_empty());
+        // The first has left the window by now.
+        deliver(&mut alerts, start, 70, "error");
+        assert!(alerts.fired.is_empty());
+        deliver(&mut alerts, start, 75, "error");
+        assert_eq!(alerts.fired.len(), 1);
+        assert_eq!(alerts.fired[0].count, 3);
+        assert!(alerts.attention_needed);
+    }
+
+    #[test]
+    fn rearms_after_firing() {
+        let mut alerts = alerts(1);
+        let start = chrono::Local::now();
+        for secs in 0..4 {
+            deliver(&mut alerts, start, secs, "error");
+        }
+        // Counting starts afresh after each alert, so a burst fires once per threshold.
+        assert_eq!(alerts.fired.len(), 2);
+    }
+
+    #[test]
+    fn only_changing_the_filter_resets_the_count() {
+        let mut alerts = alerts(2);
+        let start = chrono::Local::now();
+        deliver(&mut alerts, start, 0, "error");
+        deliver(&mut alerts, start, 1, "error");
+        alerts.rules[0].name = "failures".into();
+        alerts.rules.push(AlertRule::default());
+        alerts.compile();
+        deliver(&mut alerts, start, 2, "error");
+        assert_eq!(alerts.fired.len(), 1);
+
+        deliver(&mut alerts, start, 3, "error");
+        deliver(&mut alerts, start, 4, "error");
+        alerts.rules[0].filter_string = "err".into();
+        alerts.compile();
+        deliver(&mut alerts, start, 5, "error");
+        assert_eq!(alerts.fired.len(), 1);
+    }
+}
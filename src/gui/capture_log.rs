@@ -0,0 +1,327 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use eframe::egui::{self, Color32, Context, FontFamily, FontId, Grid, RichText, TextEdit, Window};
+
+use super::export::message_record;
+use super::model::ModelItem;
+use super::state::ProjectionColumn;
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct CaptureLogSettings {
+    pub enabled: bool,
+    pub directory: String,
+    /// Start a new file once this much (uncompressed) data has been written; 0 for no limit.
+    pub max_size_mb: u32,
+    /// Start a new file after this long; 0 for no limit.
+    pub max_age_minutes: u32,
+    pub gzip: bool,
+}
+
+impl Default for CaptureLogSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            directory: String::default(),
+            max_size_mb: 100,
+            max_age_minutes: 60,
+            gzip: false,
+        }
+    }
+}
+
+struct LogFile {
+    path: PathBuf,
+    /// Dropping the writer completes the file, including any gzip trailer.
+    writer: Box<dyn Write>,
+    opened: chrono::DateTime<chrono::Local>,
+    written: u64,
+}
+
+/// Appends every delivery to JSON Lines files on disk, independently of the in-memory
+/// model, starting a new file when the current one grows too large or too old.
+#[derive(Default)]
+pub struct CaptureLog {
+    pub settings: CaptureLogSettings,
+    file: Option<LogFile>,
+    pub error: Option<String>,
+}
+
+impl CaptureLog {
+    pub fn new(settings: CaptureLogSettings) -> Self {
+        Self {
+            settings,
+            ..Default::default()
+        }
+    }
+
+    fn open(&self) -> anyhow::Result<LogFile> {
+        let opened = chrono::Local::now();
+        let extension = if self.settings.gzip {
+            "jsonl.gz"
+        } else {
+            "jsonl"
+        };
+        let stem = format!("elmer-{}", opened.format("%Y%m%d-%H%M%S%.3f"));
+        // Files opened within the same millisecond are told apart by a sequence number,
+        // rather than one overwriting another.
+        let mut sequence = 0;
+        let (path, file) = loop {
+            let name = match sequence {
+                0 => format!("{}.{}", stem, extension),
+                n => format!("{}-{}.{}", stem, n, extension),
+            };
+            let path = Path::new(&self.settings.directory).join(name);
+            match std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+            {
+                Ok(file) => break (path, file),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => sequence += 1,
+                Err(e) => return Err(e.into()),
+            }
+        };
+        let writer: Box<dyn Write> = if self.settings.gzip {
+            Box::new(std::io::BufWriter::new(flate2::write::GzEncoder::new(
+                file,
+                flate2::Compression::default(),
+            )))
+        } else {
+            Box::new(std::io::BufWriter::new(file))
+        };
+        Ok(LogFile {
+            path,
+            writer,
+            opened,
+            written: 0,
+        })
+    }
+
+    fn needs_rotation(&self, file: &LogFile) -> bool {
+        let settings = &self.settings;
+        (settings.max_size_mb > 0 && file.written >= settings.max_size_mb as u64 * 1024 * 1024)
+            || (settings.max_age_minutes > 0
+                && chrono::Local::now() - file.opened
+                    >= chrono::TimeDelta::minutes(settings.max_age_minutes as i64))
+    }
+
+    fn try_write(
+        &mut self,
+        item: &ModelItem,
+        projection_columns: &[ProjectionColumn],
+    ) -> anyhow::Result<()> {
+        if self.file.as_ref().is_some_and(|f| self.needs_rotation(f)) {
+            self.close();
+        }
+        if self.file.is_none() {
+            self.file = Some(self.open()?);
+        }
+        let file = self.file.as_mut().expect("Log file just opened");
+        let line = format!("{}\n", message_record(item, projection_columns)?);
+        file.writer.write_all(line.as_bytes())?;
+        file.written += line.len() as u64;
+        Ok(())
+    }
+
+    pub fn write(&mut self, item: &ModelItem, projection_columns: &[ProjectionColumn]) {
+        if !self.settings.enabled {
+            return;
+        }
+        if let Err(e) = self.try_write(item, projection_columns) {
+            // Rather than failing on every subsequent message, stop until re-enabled.
+            log::error!("Failed to write capture log: {}", e);
+            self.error = Some(e.to_string());
+            self.settings.enabled = false;
+            self.close();
+        }
+    }
+
+    /// Push buffered records to disk, so that little is lost should we crash.
+    pub fn flush(&mut self) {
+        if let Some(file) = &mut self.file {
+            if let Err(e) = file.writer.flush() {
+                log::error!("Failed to flush capture log: {}", e);
+                self.error = Some(e.to_string());
+            }
+        }
+    }
+
+    /// Complete the current file, if any; the next delivery starts another.
+    pub fn close(&mut self) {
+        if let Some(mut file) = self.file.take() {
+            if let Err(e) = file.writer.flush() {
+                log::error!("Failed to close {}: {}", file.path.display(), e);
+            }
+        }
+    }
+}
+
+impl super::App {
+    pub(crate) fn capture_log_window(&mut self, ctx: &Context) {
+        let log = &mut self.capture_log;
+        let mut changed = false;
+
+        Window::new("Capture log")
+            .movable(true)
+            .resizable(false)
+            .collapsible(false)
+            .open(&mut self.gui_state.show_capture_log)
+            .show(ctx, |ui| {
+                let style = ui.style_mut();
+                style.override_font_id = Some(FontId {
+                    size: 16.0,
+                    family: FontFamily::Proportional,
+                });
+                let settings = &mut log.settings;
+                Grid::new("capture_log").num_columns(2).show(ui, |ui| {
+                    ui.label("Directory");
+                    ui.horizontal(|ui| {
+                        // Only once complete, rather than on every keystroke.
+                        changed |= ui
+                            .add(TextEdit::singleline(&mut settings.directory).hint_text("."))
+                            .lost_focus();
+                        if ui.button("Browse...").clicked() {
+                            if let Some(directory) = rfd::FileDialog::new().pick_folder() {
+                                settings.directory = directory.display().to_string();
+                                changed = true;
+                            }
+                        }
+                    });
+                    ui.end_row();
+
+                    ui.label("Rotate after");
+                    ui.horizontal(|ui| {
+                        changed |= ui
+                            .add(egui::DragValue::new(&mut settings.max_size_mb).suffix(" MiB"))
+                            .on_hover_text("0 for no size limit")
+                            .changed();
+                        ui.label("or");
+                        changed |= ui
+                            .add(egui::DragValue::new(&mut settings.max_age_minutes).suffix(" min"))
+                            .on_hover_text("0 for no time limit")
+                            .changed();
+                    });
+                    ui.end_row();
+
+                    ui.label("");
+                    changed |= ui.checkbox(&mut settings.gzip, "Compress (gzip)").changed();
+                    ui.end_row();
+
+                    ui.label("");
+                    if ui
+                        .checkbox(&mut settings.enabled, "Log every delivery")
+                        .changed()
+                    {
+                        log.error = None;
+                        changed = true;
+                    }
+                    ui.end_row();
+                });
+
+                if let Some(file) = &log.file {
+                    ui.label(format!(
+                        "Writing {} ({} bytes)",
+                        file.path.display(),
+                        file.written
+                    ));
+                }
+                if let Some(error) = &log.error {
+                    ui.label(RichText::new(error).color(Color32::RED));
+                }
+            });
+
+        // New settings take effect from a new file.
+        if changed {
+            self.capture_log.close();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+    use crate::rabbit::DeliveryInfo;
+
+    /// A capture log writing to a new, empty directory.
+    fn capture_log(gzip: bool) -> CaptureLog {
+        let directory = std::env::temp_dir().join(format!("elmer-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&directory).unwrap();
+        CaptureLog::new(CaptureLogSettings {
+            enabled: true,
+            directory: directory.display().to_string(),
+            gzip,
+            ..Default::default()
+        })
+    }
+
+    fn write(log: &mut CaptureLog, body: &str) {
+        let item = ModelItem::new("{}".into(), body.into(), DeliveryInfo::default());
+        log.write(&item, &[]);
+        assert!(log.error.is_none());
+    }
+
+    fn files(log: &CaptureLog) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = std::fs::read_dir(&log.settings.directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn old_files_are_rotated_without_overwriting() {
+        let mut log = capture_log(false);
+        write(&mut log, "first");
+        log.file.as_mut().unwrap().opened -= chrono::TimeDelta::hours(2);
+        write(&mut log, "second");
+        // Opened within the same millisecond (or not), files still get names of their own.
+        let again = log.open().unwrap();
+        assert_ne!(again.path, log.file.as_ref().unwrap().path);
+        drop(again);
+        log.close();
+
+        let files = files(&log);
+        assert_eq!(files.len(), 3);
+        let contents: Vec<String> = files
+            .iter()
+            .map(|f| std::fs::read_to_string(f).unwrap())
+            .collect();
+        assert!(contents
+            .iter()
+            .any(|c| c.contains("first") && !c.contains("second")));
+        assert!(contents
+            .iter()
+            .any(|c| c.contains("second") && !c.contains("first")));
+        std::fs::remove_dir_all(&log.settings.directory).unwrap();
+    }
+
+    #[test]
+    fn gzip_files_are_complete_once_closed() {
+        let mut log = capture_log(true);
+        write(&mut log, "first");
+        write(&mut log, "second");
+        log.close();
+
+        let files = files(&log);
+        assert_eq!(files.len(), 1);
+        let mut text = String::new();
+        flate2::read::GzDecoder::new(std::fs::File::open(&files[0]).unwrap())
+            .read_to_string(&mut text)
+            .unwrap();
+        let records: Vec<serde_json::Value> = text
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1]["body"], "second");
+        let timestamp = records[0]["timestamp"].as_str().unwrap();
+        assert!(chrono::DateTime::parse_from_rfc3339(timestamp).is_ok());
+        std::fs::remove_dir_all(&log.settings.directory).unwrap();
+    }
+}
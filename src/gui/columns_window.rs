@@ -0,0 +1,194 @@
+use eframe::egui::{
+    self, Align, Button, Color32, Context, FontFamily, FontId, Layout, RichText, TextEdit, Window,
+};
+use egui_extras::{Column, TableBuilder};
+use egui_phosphor::regular as icon;
+
+use super::state::{DataColumn, ProjectionColumn, ProjectionSource};
+
+impl super::App {
+    /// Window for arranging the columns of the data view and managing the user-defined
+    /// projection columns among them.
+    pub(crate) fn columns_window(&mut self, ctx: &Context) {
+        let mut changed = false;
+        let mut delete_index = None;
+        let mut move_column = None;
+
+        Window::new("Columns")
+            .movable(true)
+            .resizable(true)
+            .collapsible(false)
+            .open(&mut self.gui_state.show_columns)
+            .show(ctx, |ui| {
+                let style = ui.style_mut();
+                style.override_font_id = Some(FontId {
+                    size: 16.0,
+                    family: FontFamily::Proportional,
+                });
+                let layout = &mut self.gui_state.table_columns;
+                let visible_count = layout.iter().filter(|c| c.visible).count();
+                let last = layout.len().saturating_sub(1);
+                for (index, column) in layout.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        if ui
+                            .add_enabled(index > 0, Button::new(icon::ARROW_UP).frame(false))
+                            .on_hover_text("Move left")
+                            .clicked()
+                        {
+                            move_column = Some((index, index - 1));
+                        }
+                        if ui
+                            .add_enabled(index < last, Button::new(icon::ARROW_DOWN).frame(false))
+                            .on_hover_text("Move right")
+                            .clicked()
+                        {
+                            move_column = Some((index, index + 1));
+                        }
+                        // At least one column must remain to hold expanded messages.
+                        ui.add_enabled(
+                            !column.visible || visible_count > 1,
+                            egui::Checkbox::new(
+                                &mut column.visible,
+                                column.column.label(&self.gui_state.projection_columns),
+                            ),
+                        );
+                    });
+                }
+                ui.separator();
+
+                let available_height = ui.available_height();
+                let table = TableBuilder::new(ui)
+                    .striped(false)
+                    .resizable(false)
+                    .cell_layout(Layout::right_to_left(Align::Center))
+                    .column(Column::remainder())
+                    .column(Column::auto())
+                    .column(Column::remainder())
+                    .column(Column::auto().at_least(32.0))
+                    .auto_shrink(false)
+                    .max_scroll_height(available_height - 120.0); // leave room for window decorations etc
+
+                let columns = &mut self.gui_state.projection_columns;
+                table
+                    .header(16.0, |mut header| {
+                        header.col(|ui| {
+                            ui.label("Name");
+                        });
+                        header.col(|ui| {
+                            ui.label("Source");
+                        });
+                        header.col(|ui| {
+                            ui.label("JSONPath");
+                        });
+                        header.col(|ui| {
+                            if ui
+                                .add(
+                                    Button::new(RichText::new(icon::PLUS).color(Color32::GREEN))
+                                        .fill(Color32::TRANSPARENT),
+                                )
+                                .on_hover_text("Add column")
+                                .clicked()
+                            {
+                                columns.push(ProjectionColumn::default());
+                                changed = true;
+                            }
+                        });
+                    })
+                    .body(|mut body| {
+                        for (index, column) in columns.iter_mut().enumerate() {
+                            body.row(16.0, |mut row| {
+                                row.col(|ui| {
+                                    changed |= ui
+                                        .add(
+                                            TextEdit::singleline(&mut column.name)
+                                                .hint_text("Order ID"),
+                                        )
+                                        .changed();
+                                });
+                                row.col(|ui| {
+                                    egui::ComboBox::from_id_salt(("source", index))
+                                        .selected_text(match column.source {
+                                            ProjectionSource::Headers => "Headers",
+                                            ProjectionSource::Body => "Body",
+                                        })
+                                        .show_ui(ui, |ui| {
+                                            changed |= ui
+                                                .selectable_value(
+                                                    &mut column.source,
+                                                    ProjectionSource::Headers,
+                                                    "Headers",
+                                                )
+                                                .changed();
+                                            changed |= ui
+                                                .selectable_value(
+                                                    &mut column.source,
+                                                    ProjectionSource::Body,
+                                                    "Body",
+                                                )
+                                                .changed();
+                                        });
+                                });
+                                row.col(|ui| {
+                                    changed |= ui
+                                        .add(
+                                            TextEdit::singleline(&mut column.path)
+                                                .hint_text("$.order.id"),
+                                        )
+                                        .changed();
+                                });
+                                row.col(|ui| {
+                                    if ui
+                                        .add(
+                                            Button::new(
+                                                RichText::new(icon::TRASH).color(Color32::RED),
+                                            )
+                                            .fill(Color32::TRANSPARENT),
+                                        )
+                                        .clicked()
+                                    {
+                                        delete_index = Some(index);
+                                    }
+                                });
+                            })
+                        }
+                    });
+            });
+
+        if let Some((from, to)) = move_column {
+            self.gui_state.table_columns.swap(from, to);
+        }
+        if let Some(index) = delete_index {
+            self.gui_state.projection_columns.remove(index);
+            // Sorting and table layouts refer to columns by index, so must be adjusted (or
+            // abandoned) accordingly.
+            if let Some(sort) = &mut self.gui_state.sort {
+                match sort.column {
+                    DataColumn::Projection(i) if i == index => self.gui_state.sort = None,
+                    DataColumn::Projection(i) if i > index => {
+                        sort.column = DataColumn::Projection(i - 1)
+                    }
+                    _ => (),
+                }
+            }
+            let layouts = std::iter::once(&mut self.gui_state.table_columns)
+                .chain(self.gui_state.table_layouts.values_mut());
+            for layout in layouts {
+                layout.retain(|c| c.column != DataColumn::Projection(index));
+                for column in layout.iter_mut() {
+                    if let DataColumn::Projection(i) = &mut column.column {
+                        if *i > index {
+                            *i -= 1;
+                        }
+                    }
+                }
+            }
+            changed = true;
+        }
+        if changed {
+            self.gui_state.sync_table_columns();
+            self.gui_data
+                .project_all(&self.gui_state.projection_columns);
+            self.gui_data.sort(self.gui_state.sort);
+        }
+    }
+}
@@ -1,4 +1,6 @@
 use super::{enums::ModalResult, prelude::*};
+use crate::rabbit::ExchangeType;
+use egui_phosphor::regular as icon;
 
 fn modal_label(ui: &mut Ui, label: &str, binding: &mut String, password: bool) -> Response {
     let label1 = ui.label(RichText::new(label).size(16.0));
@@ -33,9 +35,6 @@ impl App {
                     changed.push(modal_label(ui, "Virtual Host", &mut con.vhost, false).changed());
                     ui.end_row();
 
-                    changed.push(modal_label(ui, "Exchange", &mut con.exchange, false).changed());
-                    ui.end_row();
-
                     changed.push(modal_label(ui, "Username", &mut con.username, false).changed());
                     ui.end_row();
 
@@ -59,11 +58,117 @@ impl App {
                     );
                     ui.end_row();
 
+                    if con.wildcard {
+                        let mut delete_index = None;
+                        for (index, exchange) in con.wildcard_exchanges.iter_mut().enumerate() {
+                            ui.label(RichText::new("Exchange").size(16.0));
+                            ui.horizontal(|ui| {
+                                changed.push(
+                                    ui.add_sized(
+                                        [120.0, 24.0],
+                                        TextEdit::singleline(&mut exchange.name)
+                                            .vertical_align(egui::Align::Center)
+                                            .font(FontId {
+                                                size: 16.0,
+                                                family: FontFamily::Proportional,
+                                            }),
+                                    )
+                                    .changed(),
+                                );
+                                egui::ComboBox::from_id_salt(("wildcard_exchange_type", index))
+                                    .width(72.0)
+                                    .selected_text(
+                                        RichText::new(exchange.exchange_type.to_string())
+                                            .size(16.0),
+                                    )
+                                    .show_ui(ui, |ui| {
+                                        for exchange_type in ExchangeType::ALL {
+                                            ui.selectable_value(
+                                                &mut exchange.exchange_type,
+                                                exchange_type,
+                                                RichText::new(exchange_type.to_string()).size(16.0),
+                                            );
+                                        }
+                                    });
+                                if ui
+                                    .add(
+                                        Button::new(RichText::new(icon::TRASH).color(Color32::RED))
+                                            .fill(Color32::TRANSPARENT),
+                                    )
+                                    .clicked()
+                                {
+                                    delete_index = Some(index);
+                                }
+                            });
+                            ui.end_row();
+                        }
+                        if let Some(index) = delete_index {
+                            con.wildcard_exchanges.remove(index);
+                            changed.push(true);
+                        }
+
+                        ui.label("");
+                        if ui
+                            .add(
+                                Button::new(
+                                    RichText::new(format!("{} Exchange", icon::PLUS))
+                                        .color(Color32::GREEN)
+                                        .size(16.0),
+                                )
+                                .fill(Color32::TRANSPARENT),
+                            )
+                            .clicked()
+                        {
+                            con.wildcard_exchanges.push(Default::default());
+                            changed.push(true);
+                        }
+                        ui.end_row();
+                    }
+
+                    ui.label(RichText::new("Subscription set").size(16.0));
+                    egui::ComboBox::from_id_salt("subscription_set")
+                        .width(200.0)
+                        .selected_text(
+                            RichText::new(con.subscription_set.as_deref().unwrap_or("(none)"))
+                                .size(16.0),
+                        )
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(
+                                &mut con.subscription_set,
+                                None,
+                                RichText::new("(none)").size(16.0),
+                            );
+                            for set in &self.gui_state.subscription_sets {
+                                ui.selectable_value(
+                                    &mut con.subscription_set,
+                                    Some(set.name.clone()),
+                                    RichText::new(&set.name).size(16.0),
+                                );
+                            }
+                        });
+                    ui.end_row();
+
                     if changed.iter().find(|b| **b).is_some() {
                         con.validate();
                     }
                 });
 
+                if con.wildcard {
+                    for exchange in &con.wildcard_exchanges {
+                        if exchange.exchange_type == ExchangeType::Direct {
+                            ui.label(
+                                RichText::new(format!(
+                                    "{} Direct exchange '{}': only catches an empty routing key",
+                                    icon::WARNING,
+                                    exchange.name
+                                ))
+                                .color(Color32::YELLOW)
+                                .size(16.0),
+                            );
+                        }
+                    }
+                }
+
                 // Filthy hack to make layout work here.
                 ui.horizontal(|ui| {
                     ui.add_enabled_ui(con.validation_error.is_none(), |ui| {
@@ -92,8 +197,12 @@ impl App {
 
             match modal_result {
                 ModalResult::Ok => {
-                    self.gui_state.connection_parameters = con.clone();
+                    let parameters = con.clone();
+                    // Each connection profile has its own table layout.
+                    self.gui_state.store_table_layout();
+                    self.gui_state.connection_parameters = parameters;
                     self.gui_state.connection_parameters.validate();
+                    self.gui_state.restore_table_layout();
                     self.close_connection_modal();
                     self.connection_manager
                         .tx
@@ -0,0 +1,410 @@
+use eframe::egui::{Color32, Context, FontFamily, FontId, Grid, RichText, ScrollArea, Ui, Window};
+use serde_json::Value;
+
+use super::model::ModelItem;
+
+/// Beyond this many lines (in product) a textual diff is too costly to compute.
+const MAX_LINE_DIFF_CELLS: usize = 4_000_000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Change {
+    Unchanged,
+    Added,
+    Removed,
+    Changed,
+}
+
+impl Change {
+    fn colour(&self) -> Color32 {
+        match self {
+            Change::Unchanged => Color32::GRAY,
+            Change::Added => Color32::LIGHT_GREEN,
+            Change::Removed => Color32::LIGHT_RED,
+            Change::Changed => Color32::YELLOW,
+        }
+    }
+}
+
+/// A difference (or not) at a single path within two JSON documents.
+struct FieldDiff {
+    path: String,
+    change: Change,
+    left: Option<String>,
+    right: Option<String>,
+}
+
+fn display_value(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        v => v.to_string(),
+    }
+}
+
+/// Compare two JSON documents field by field. Objects are compared by key, so key order
+/// is immaterial, and arrays element by element.
+fn structural_diff(
+    path: &str,
+    left: Option<&Value>,
+    right: Option<&Value>,
+    out: &mut Vec<FieldDiff>,
+) {
+    match (left, right) {
+        (Some(Value::Object(l)), Some(Value::Object(r))) => {
+            let mut keys: Vec<&String> = l.keys().chain(r.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                structural_diff(&format!("{}.{}", path, key), l.get(key), r.get(key), out);
+            }
+        }
+        (Some(Value::Array(l)), Some(Value::Array(r))) => {
+            for index in 0..l.len().max(r.len()) {
+                structural_diff(
+                    &format!("{}[{}]", path, index),
+                    l.get(index),
+                    r.get(index),
+                    out,
+                );
+            }
+        }
+        (left, right) => {
+            let change = match (left, right) {
+                (None, None) => return,
+                (Some(_), None) => Change::Removed,
+                (None, Some(_)) => Change::Added,
+                (Some(l), Some(r)) if l == r => Change::Unchanged,
+                _ => Change::Changed,
+            };
+            out.push(FieldDiff {
+                path: path.to_string(),
+                change,
+                left: left.map(display_value),
+                right: right.map(display_value),
+            });
+        }
+    }
+}
+
+/// Line by line difference of two texts by longest common subsequence, as pairs of
+/// (left line, right line) where either side is absent for removed/added lines.
+fn line_diff<'a>(left: &'a str, right: &'a str) -> Vec<(Option<&'a str>, Option<&'a str>)> {
+    let l: Vec<&str> = left.lines().collect();
+    let r: Vec<&str> = right.lines().collect();
+    if l.len() * r.len() > MAX_LINE_DIFF_CELLS {
+        return l
+            .iter()
+            .map(|l| (Some(*l), None))
+            .chain(r.iter().map(|r| (None, Some(*r))))
+            .collect();
+    }
+
+    // lcs[i][j] is the length of the longest common subsequence of l[i..] and r[j..]
+    let mut lcs = vec![vec![0usize; r.len() + 1]; l.len() + 1];
+    for i in (0..l.len()).rev() {
+        for j in (0..r.len()).rev() {
+            lcs[i][j] = if l[i] == r[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < l.len() || j < r.len() {
+        if i < l.len() && j < r.len() && l[i] == r[j] {
+            lines.push((Some(l[i]), Some(r[j])));
+            i += 1;
+            j += 1;
+        } else if j < r.len() && (i == l.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            lines.push((None, Some(r[j])));
+            j += 1;
+        } else {
+            lines.push((Some(l[i]), None));
+            i += 1;
+        }
+    }
+    lines
+}
+
+/// JSON is pretty printed (with keys in a canonical order) so that it may be compared by line.
+fn diff_text(text: &str) -> String {
+    match serde_json::from_str::<Value>(text) {
+        Ok(json) => serde_json::to_string_pretty(&json).unwrap_or_else(|_| text.to_string()),
+        Err(_) => text.to_string(),
+    }
+}
+
+fn item_summary(item: &ModelItem) -> String {
+    format!(
+        "{}  {} / {}",
+        item.timestamp, item.info.exchange, item.info.routing_key
+    )
+}
+
+/// The comparison of one part (headers or body) of two messages.
+enum SectionDiff {
+    /// A structural comparison was requested, but a side is not JSON.
+    NotJson,
+    Fields(Vec<FieldDiff>),
+    Lines(Vec<(Option<String>, Option<String>)>),
+}
+
+impl SectionDiff {
+    fn new(left: &str, right: &str, structural: bool) -> Self {
+        if !structural {
+            let (left, right) = (diff_text(left), diff_text(right));
+            return Self::Lines(
+                line_diff(&left, &right)
+                    .into_iter()
+                    .map(|(l, r)| (l.map(str::to_string), r.map(str::to_string)))
+                    .collect(),
+            );
+        }
+        let (Ok(left), Ok(right)) = (
+            serde_json::from_str::<Value>(left),
+            serde_json::from_str::<Value>(right),
+        ) else {
+            return Self::NotJson;
+        };
+        let mut fields = Vec::new();
+        structural_diff("$", Some(&left), Some(&right), &mut fields);
+        Self::Fields(fields)
+    }
+
+    fn identical(&self) -> bool {
+        match self {
+            Self::NotJson => false,
+            Self::Fields(fields) => fields.iter().all(|f| f.change == Change::Unchanged),
+            Self::Lines(lines) => lines.iter().all(|(l, r)| l.is_some() && r.is_some()),
+        }
+    }
+
+    fn show(&self, ui: &mut Ui, id: &str, show_unchanged: bool) {
+        if let Self::NotJson = self {
+            ui.label(RichText::new("Not JSON; see the textual comparison").color(Color32::GRAY));
+            return;
+        }
+        if self.identical() {
+            ui.label(RichText::new("Identical").color(Color32::GRAY));
+            if !show_unchanged {
+                return;
+            }
+        }
+        Grid::new(id).striped(true).show(ui, |ui| match self {
+            Self::NotJson => (),
+            Self::Fields(fields) => {
+                for field in fields
+                    .iter()
+                    .filter(|f| show_unchanged || f.change != Change::Unchanged)
+                {
+                    let colour = field.change.colour();
+                    ui.label(RichText::new(&field.path).color(colour));
+                    ui.label(
+                        RichText::new(field.left.as_deref().unwrap_or_default()).color(colour),
+                    );
+                    ui.label(
+                        RichText::new(field.right.as_deref().unwrap_or_default()).color(colour),
+                    );
+                    ui.end_row();
+                }
+            }
+            Self::Lines(lines) => {
+                for (l, r) in lines {
+                    let change = match (l, r) {
+                        (Some(_), Some(_)) => Change::Unchanged,
+                        (Some(_), None) => Change::Removed,
+                        _ => Change::Added,
+                    };
+                    if change == Change::Unchanged && !show_unchanged {
+                        continue;
+                    }
+                    let colour = change.colour();
+                    ui.label(
+                        RichText::new(l.as_deref().unwrap_or_default())
+                            .monospace()
+                            .color(colour),
+                    );
+                    ui.label(
+                        RichText::new(r.as_deref().unwrap_or_default())
+                            .monospace()
+                            .color(colour),
+                    );
+                    ui.end_row();
+                }
+            }
+        });
+    }
+}
+
+/// The comparison shown in the diff window, computed once for each pair of messages and
+/// mode rather than every frame.
+pub struct Comparison {
+    items: (u64, u64),
+    structural: bool,
+    headers: SectionDiff,
+    body: SectionDiff,
+}
+
+impl Comparison {
+    fn new(items: (u64, u64), left: &ModelItem, right: &ModelItem, structural: bool) -> Self {
+        Self {
+            items,
+            structural,
+            headers: SectionDiff::new(&left.headers, &right.headers, structural),
+            body: SectionDiff::new(&left.body, &right.body, structural),
+        }
+    }
+}
+
+impl super::App {
+    /// Window comparing the headers and bodies of the two messages selected for comparison.
+    pub(crate) fn diff_window(&mut self, ctx: &Context) {
+        let Some(pair @ (left, right)) = self.gui_state.diff_items else {
+            return;
+        };
+        let mut open = true;
+        let state = &mut self.gui_state;
+        let items = self.gui_data.get(left).zip(self.gui_data.get(right));
+
+        Window::new("Compare messages")
+            .movable(true)
+            .resizable(true)
+            .collapsible(false)
+            .open(&mut open)
+            .show(ctx, |ui| {
+                let style = ui.style_mut();
+                style.override_font_id = Some(FontId {
+                    size: 16.0,
+                    family: FontFamily::Proportional,
+                });
+                let Some((left, right)) = items else {
+                    ui.label("These messages are no longer available");
+                    return;
+                };
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut state.diff_structural, true, "Structural");
+                    ui.radio_value(&mut state.diff_structural, false, "Textual");
+                    ui.checkbox(&mut state.diff_show_unchanged, "Show unchanged");
+                });
+                ui.label(
+                    RichText::new(format!("Left:  {}", item_summary(left)))
+                        .color(Color32::LIGHT_RED),
+                );
+                ui.label(
+                    RichText::new(format!("Right: {}", item_summary(right)))
+                        .color(Color32::LIGHT_GREEN),
+                );
+                ui.separator();
+
+                let show_unchanged = state.diff_show_unchanged;
+                let structural = state.diff_structural;
+                let comparison = match state.diff_comparison.take() {
+                    Some(c) if c.items == pair && c.structural == structural => c,
+                    _ => Comparison::new(pair, left, right, structural),
+                };
+                let comparison = state.diff_comparison.insert(comparison);
+                ScrollArea::both().auto_shrink(false).show(ui, |ui| {
+                    ui.label(RichText::new("Headers").strong());
+                    comparison.headers.show(ui, "diff_headers", show_unchanged);
+                    ui.separator();
+                    ui.label(RichText::new("Body").strong());
+                    comparison.body.show(ui, "diff_body", show_unchanged);
+                });
+            });
+
+        if !open {
+            self.gui_state.diff_items = None;
+            self.gui_state.diff_comparison = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diff(left: &str, right: &str) -> Vec<(String, Change, Option<String>, Option<String>)> {
+        let (left, right): (Value, Value) = (
+            serde_json::from_str(left).unwrap(),
+            serde_json::from_str(right).unwrap(),
+        );
+        let mut out = Vec::new();
+        structural_diff("$", Some(&left), Some(&right), &mut out);
+        out.into_iter()
+            .map(|d| (d.path, d.change, d.left, d.right))
+            .collect()
+    }
+
+    fn field(
+        path: &str,
+        change: Change,
+        left: Option<&str>,
+        right: Option<&str>,
+    ) -> (String, Change, Option<String>, Option<String>) {
+        (
+            path.into(),
+            change,
+            left.map(Into::into),
+            right.map(Into::into),
+        )
+    }
+
+    #[test]
+    fn fields_are_compared_by_key() {
+        let fields = diff(
+            r#"{"id": 1, "name": "a", "old": true}"#,
+            r#"{"new": null, "name": "b", "id": 1}"#,
+        );
+        assert_eq!(
+            fields,
+            vec![
+                field("$.id", Change::Unchanged, Some("1"), Some("1")),
+                field("$.name", Change::Changed, Some("a"), Some("b")),
+                field("$.new", Change::Added, None, Some("null")),
+                field("$.old", Change::Removed, Some("true"), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn nested_fields_and_elements_are_compared() {
+        let fields = diff(
+            r#"{"order": {"lines": [1, 2], "customer": {"id": 7}}}"#,
+            r#"{"order": {"lines": [1, 3, 4], "customer": 7}}"#,
+        );
+        assert_eq!(
+            fields,
+            vec![
+                field(
+                    "$.order.customer",
+                    Change::Changed,
+                    Some(r#"{"id":7}"#),
+                    Some("7")
+                ),
+                field("$.order.lines[0]", Change::Unchanged, Some("1"), Some("1")),
+                field("$.order.lines[1]", Change::Changed, Some("2"), Some("3")),
+                field("$.order.lines[2]", Change::Added, None, Some("4")),
+            ]
+        );
+    }
+
+    #[test]
+    fn lines_are_paired_by_longest_common_subsequence() {
+        let lines = line_diff("a\nb\nc\nd", "a\nc\nx\nd\ne");
+        assert_eq!(
+            lines,
+            vec![
+                (Some("a"), Some("a")),
+                (Some("b"), None),
+                (Some("c"), Some("c")),
+                (None, Some("x")),
+                (Some("d"), Some("d")),
+                (None, Some("e")),
+            ]
+        );
+        assert!(line_diff("", "").is_empty());
+        assert_eq!(line_diff("a", ""), vec![(Some("a"), None)]);
+    }
+}
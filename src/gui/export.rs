@@ -0,0 +1,61 @@
+use std::io::Write;
+
+use serde_json::json;
+
+use super::model::ModelItem;
+use super::state::ProjectionColumn;
+
+impl super::App {
+    /// Prompt for a destination, then write every message that satisfies the current
+    /// filter to it as JSON Lines, including the values of any projection columns and the
+    /// user's bookmarks and notes.
+    pub(crate) fn export_messages(&self) {
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("JSON Lines", &["jsonl"])
+            .set_file_name("elmer.jsonl")
+            .save_file()
+        else {
+            return;
+        };
+
+        if let Err(e) = self.write_export(&path) {
+            log::error!("Failed to export to {}: {}", path.display(), e);
+        }
+    }
+
+    fn write_export(&self, path: &std::path::Path) -> anyhow::Result<()> {
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(path)?);
+        for item in self.gui_data.data.iter().filter(|i| i.matches_filter) {
+            let record = message_record(item, &self.gui_state.projection_columns)?;
+            writeln!(writer, "{}", record)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// The JSON Lines representation of a message, as exported or logged.
+pub(crate) fn message_record(
+    item: &ModelItem,
+    projection_columns: &[ProjectionColumn],
+) -> anyhow::Result<serde_json::Value> {
+    let columns: serde_json::Map<String, serde_json::Value> = projection_columns
+        .iter()
+        .zip(&item.projections)
+        .map(|(column, value)| (column.name.clone(), json!(value)))
+        .collect();
+    Ok(json!({
+        "timestamp": item.received.to_rfc3339(),
+        "exchange": item.info.exchange,
+        "routing_key": item.info.routing_key,
+        "content_type": item.info.content_type,
+        "correlation_id": item.info.correlation_id,
+        "reply_to": item.info.reply_to,
+        "message_id": item.info.message_id,
+        "headers": serde_json::from_str::<serde_json::Value>(&item.headers)?,
+        "body": *item.body,
+        "columns": columns,
+        "bookmarked": item.bookmarked,
+        "note": item.note,
+    }))
+}
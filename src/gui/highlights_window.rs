@@ -0,0 +1,108 @@
+use eframe::egui::{
+    Align, Button, Color32, Context, FontFamily, FontId, Layout, RichText, TextEdit, Window,
+};
+use egui_extras::{Column, TableBuilder};
+use egui_phosphor::regular as icon;
+
+use super::state::HighlightRule;
+
+impl super::App {
+    /// Window for editing the highlight rules, which mark matching text in their own colours
+    /// whether or not it is filtered on.
+    pub(crate) fn highlights_window(&mut self, ctx: &Context) {
+        let mut changed = false;
+        let mut delete_index = None;
+
+        Window::new("Highlights")
+            .movable(true)
+            .resizable(true)
+            .collapsible(false)
+            .open(&mut self.gui_state.show_highlights)
+            .show(ctx, |ui| {
+                let style = ui.style_mut();
+                style.override_font_id = Some(FontId {
+                    size: 16.0,
+                    family: FontFamily::Proportional,
+                });
+                let rules = &mut self.gui_state.highlight_rules;
+                TableBuilder::new(ui)
+                    .id_salt("highlight_rules")
+                    .striped(false)
+                    .resizable(false)
+                    .cell_layout(Layout::left_to_right(Align::Center))
+                    .column(Column::auto())
+                    .column(Column::remainder().at_least(200.0))
+                    .column(Column::auto())
+                    .column(Column::auto().at_least(32.0))
+                    .auto_shrink([false, true])
+                    .header(16.0, |mut header| {
+                        for heading in ["", "Regex", "Colour"] {
+                            header.col(|ui| {
+                                ui.label(heading);
+                            });
+                        }
+                        header.col(|ui| {
+                            if ui
+                                .add(
+                                    Button::new(RichText::new(icon::PLUS).color(Color32::GREEN))
+                                        .fill(Color32::TRANSPARENT),
+                                )
+                                .on_hover_text("Add rule")
+                                .clicked()
+                            {
+                                rules.push(HighlightRule::default());
+                            }
+                        });
+                    })
+                    .body(|mut body| {
+                        for (index, rule) in rules.iter_mut().enumerate() {
+                            body.row(20.0, |mut row| {
+                                row.col(|ui| {
+                                    changed |= ui
+                                        .checkbox(&mut rule.enabled, "")
+                                        .on_hover_text("Enabled")
+                                        .changed();
+                                });
+                                row.col(|ui| {
+                                    let invalid = rule.regex.is_none() && !rule.pattern.is_empty();
+                                    let mut edit = TextEdit::singleline(&mut rule.pattern)
+                                        .hint_text("error|fail");
+                                    if invalid {
+                                        edit = edit.text_color(Color32::RED);
+                                    }
+                                    if ui.add(edit).changed() {
+                                        rule.compile();
+                                        changed = true;
+                                    }
+                                });
+                                row.col(|ui| {
+                                    changed |=
+                                        ui.color_edit_button_srgba(&mut rule.colour).changed();
+                                });
+                                row.col(|ui| {
+                                    if ui
+                                        .add(
+                                            Button::new(
+                                                RichText::new(icon::TRASH).color(Color32::RED),
+                                            )
+                                            .fill(Color32::TRANSPARENT),
+                                        )
+                                        .clicked()
+                                    {
+                                        delete_index = Some(index);
+                                    }
+                                });
+                            });
+                        }
+                    });
+            });
+
+        if let Some(index) = delete_index {
+            self.gui_state.highlight_rules.remove(index);
+            changed = true;
+        }
+        if changed {
+            self.gui_data.highlight_all(&self.gui_state.highlight_rules);
+        }
+    }
+}
@@ -0,0 +1,232 @@
+use eframe::egui::{self, text::LayoutJob, CollapsingHeader, Color32, Response, Ui};
+use serde_json::Value;
+
+use super::state::HighlightRule;
+use super::tree_data_view::{highlight_format, layered_highlight_job};
+
+/// What is highlighted within the labels of a tree.
+struct Marks<'a> {
+    regex: Option<&'a regex::Regex>,
+    rules: &'a [HighlightRule],
+    /// The path of the node holding the current match of `regex`.
+    current: Option<&'a str>,
+}
+
+/// Render a JSON value as a tree of collapsible nodes. Objects and arrays may be
+/// expanded individually; leaves (and nodes) offer copy path/value actions via the
+/// context menu. Any matches for `regex` within keys or scalar values are highlighted,
+/// distinctly in the node at the path `current`, over those of the highlight `rules`.
+pub(crate) fn json_tree(
+    ui: &mut Ui,
+    id_salt: impl std::hash::Hash,
+    value: &Value,
+    regex: Option<&regex::Regex>,
+    rules: &[HighlightRule],
+    current: Option<&str>,
+) {
+    let marks = Marks {
+        regex,
+        rules,
+        current,
+    };
+    ui.push_id(id_salt, |ui| {
+        json_node(ui, None, "$".into(), value, &marks, 0);
+    });
+}
+
+/// The JSONPath (as shown in the tree) of the key or scalar value of the JSON `text` which
+/// contains the byte `offset`, if any does.
+pub(crate) fn json_path_at(text: &str, offset: usize) -> Option<String> {
+    enum Container {
+        /// The current key, and whether one is expected next.
+        Object(Option<String>, bool),
+        Array(usize),
+    }
+    fn path(stack: &[Container]) -> String {
+        stack
+            .iter()
+            .fold("$".to_string(), |path, container| match container {
+                Container::Object(Some(key), _) => child_path(&path, key),
+                Container::Object(None, _) => path,
+                Container::Array(index) => format!("{}[{}]", path, index),
+            })
+    }
+
+    let bytes = text.as_bytes();
+    let mut stack = Vec::new();
+    let mut position = 0;
+    while position < bytes.len() {
+        let start = position;
+        match bytes[position] {
+            b'{' => stack.push(Container::Object(None, true)),
+            b'[' => stack.push(Container::Array(0)),
+            b'}' | b']' => {
+                stack.pop();
+            }
+            b':' => {
+                if let Some(Container::Object(_, expecting_key)) = stack.last_mut() {
+                    *expecting_key = false;
+                }
+            }
+            b',' => match stack.last_mut() {
+                Some(Container::Object(_, expecting_key)) => *expecting_key = true,
+                Some(Container::Array(index)) => *index += 1,
+                None => (),
+            },
+            b if b.is_ascii_whitespace() => (),
+            b'"' => {
+                position += 1;
+                while position < bytes.len() && bytes[position] != b'"' {
+                    position += if bytes[position] == b'\\' { 2 } else { 1 };
+                }
+                let end = (position + 1).min(bytes.len());
+                if let Some(Container::Object(key, true)) = stack.last_mut() {
+                    *key = serde_json::from_str(&text[start..end]).ok();
+                }
+                if (start..end).contains(&offset) {
+                    return Some(path(&stack));
+                }
+            }
+            _ => {
+                while position + 1 < bytes.len() && !b",}] \t\r\n".contains(&bytes[position + 1]) {
+                    position += 1;
+                }
+                if (start..=position).contains(&offset) {
+                    return Some(path(&stack));
+                }
+            }
+        }
+        position += 1;
+    }
+    None
+}
+
+/// The JSONPath of a child of `parent`, e.g. `$.headers.x-death[0]`
+fn child_path(parent: &str, key: &str) -> String {
+    if key
+        .chars()
+        .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+    {
+        format!("{}.{}", parent, key)
+    } else {
+        format!("{}[{}]", parent, Value::String(key.into()))
+    }
+}
+
+fn node_label(key: Option<&str>, summary: &str, marks: &Marks, current: bool) -> LayoutJob {
+    let text = match key {
+        Some(key) => format!("{}: {}", key, summary),
+        None => summary.to_string(),
+    };
+    let rule_spans = marks
+        .rules
+        .iter()
+        .filter(|rule| rule.enabled)
+        .filter_map(|rule| Some((rule.regex.as_ref()?, rule.colour)));
+    let filter_colour = if current {
+        Color32::GOLD
+    } else {
+        Color32::LIGHT_BLUE
+    };
+    let spans: Vec<_> = rule_spans
+        .chain(marks.regex.map(|regex| (regex, filter_colour)))
+        .flat_map(|(regex, colour)| {
+            regex
+                .find_iter(&text)
+                .map(move |m| (m.start(), m.end(), highlight_format(colour)))
+        })
+        .collect();
+    layered_highlight_job(&text, &spans)
+}
+
+fn copy_menu(response: &Response, path: &str, value: &Value) {
+    response.context_menu(|ui| {
+        if ui.button("Copy path").clicked() {
+            ui.ctx().copy_text(path.to_string());
+            ui.close_menu();
+        }
+        if ui.button("Copy value").clicked() {
+            let text = match value {
+                Value::String(s) => s.clone(),
+                v => serde_json::to_string_pretty(v).unwrap_or_default(),
+            };
+            ui.ctx().copy_text(text);
+            ui.close_menu();
+        }
+    });
+}
+
+fn json_node(
+    ui: &mut Ui,
+    key: Option<&str>,
+    path: String,
+    value: &Value,
+    marks: &Marks,
+    depth: usize,
+) {
+    let label = |summary: &str| node_label(key, summary, marks, marks.current == Some(&path));
+    match value {
+        Value::Object(map) => {
+            let summary = format!("{{{}}}", map.len());
+            let response = CollapsingHeader::new(label(&summary))
+                .id_salt(&path)
+                .default_open(depth == 0)
+                .show(ui, |ui| {
+                    for (k, v) in map {
+                        let path = child_path(&path, k);
+                        json_node(ui, Some(k), path, v, marks, depth + 1);
+                    }
+                });
+            copy_menu(&response.header_response, &path, value);
+        }
+        Value::Array(arr) => {
+            let summary = format!("[{}]", arr.len());
+            let response = CollapsingHeader::new(label(&summary))
+                .id_salt(&path)
+                .default_open(depth == 0)
+                .show(ui, |ui| {
+                    for (i, v) in arr.iter().enumerate() {
+                        let index = i.to_string();
+                        let path = format!("{}[{}]", path, i);
+                        json_node(ui, Some(&index), path, v, marks, depth + 1);
+                    }
+                });
+            copy_menu(&response.header_response, &path, value);
+        }
+        scalar => {
+            let response = ui.add(
+                egui::Label::new(label(&scalar.to_string()))
+                    .wrap()
+                    .sense(egui::Sense::click()),
+            );
+            copy_menu(&response, &path, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_are_found_for_keys_and_values() {
+        let text = r#"{"id": 7, "tags": ["a", "b,c"], "x-death": {"count": true}, "a b": null}"#;
+        let path_of = |needle: &str| json_path_at(text, text.find(needle).unwrap());
+        assert_eq!(path_of("id").as_deref(), Some("$.id"));
+        assert_eq!(path_of("7").as_deref(), Some("$.id"));
+        assert_eq!(path_of("\"a\"").as_deref(), Some("$.tags[0]"));
+        assert_eq!(path_of("c\"").as_deref(), Some("$.tags[1]"));
+        assert_eq!(path_of("count").as_deref(), Some("$.x-death.count"));
+        assert_eq!(path_of("true").as_deref(), Some("$.x-death.count"));
+        assert_eq!(path_of("null").as_deref(), Some("$[\"a b\"]"));
+    }
+
+    #[test]
+    fn no_path_is_found_between_tokens() {
+        let text = r#"{"a": [1, 2], "b\"c": 3}"#;
+        assert_eq!(json_path_at(text, 0), None);
+        assert_eq!(json_path_at(text, text.find(',').unwrap()), None);
+        let path = json_path_at(text, text.find('3').unwrap());
+        assert_eq!(path.as_deref(), Some(r#"$["b\"c"]"#));
+    }
+}
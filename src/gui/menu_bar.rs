@@ -4,6 +4,7 @@ use egui::Ui;
 use egui_phosphor::regular as icon;
 
 use super::add_subscription_window::SubscriptionParams;
+use super::state::{SavedFilter, ScrollTarget};
 
 impl super::App {
     /// Open the connection settings window e.g. to connect to a different
@@ -16,8 +17,9 @@ impl super::App {
                 .on_hover_text("Connection settings")
                 .clicked()
             {
-                self.gui_state.connection_modal_parameters =
-                    Some(self.gui_state.connection_parameters.clone());
+                let mut parameters = self.gui_state.connection_parameters.clone();
+                parameters.validate();
+                self.gui_state.connection_modal_parameters = Some(parameters);
             }
         } else {
             ui.add_enabled(false, Button::new(RichText::new(icon).size(24.0)));
@@ -60,7 +62,77 @@ impl super::App {
             .on_hover_text("Clear data")
             .clicked()
         {
-            self.gui_data.data.clear();
+            self.gui_data.clear();
+        }
+    }
+
+    /// Pause (or resume) the display of arriving messages. Whilst paused, messages are held
+    /// back (or, optionally, left on the broker) and their number shown alongside.
+    fn pause_button(&mut self, ui: &mut Ui) {
+        let paused = self.gui_data.paused;
+        let (glyph, colour, hover_text) = if paused {
+            (icon::PLAY, Color32::YELLOW, "Resume capture")
+        } else {
+            (icon::PAUSE, Color32::WHITE, "Pause capture")
+        };
+        if ui
+            .button(RichText::new(glyph).size(24.0).color(colour))
+            .on_hover_text(hover_text)
+            .clicked()
+        {
+            if paused {
+                self.gui_data.resume(self.gui_state.sort);
+            } else {
+                self.gui_data.paused = true;
+            }
+            // Always resume consuming, since the option may have been unticked since the
+            // consumer was cancelled; resuming an active consumer does nothing.
+            if (paused || self.gui_state.pause_cancels_consumer)
+                && self.gui_state.connection == ConnectionStatus::Connected
+            {
+                self.connection_manager.set_consuming(paused);
+            }
+        }
+        if paused {
+            ui.label(
+                RichText::new(self.gui_data.held_count().to_string())
+                    .color(Color32::BLACK)
+                    .background_color(Color32::YELLOW),
+            )
+            .on_hover_text("Messages arrived since pausing");
+        }
+    }
+
+    /// Jump to the newest message and keep following as more arrive; click again to stop.
+    fn follow_button(&mut self, ui: &mut Ui) {
+        let following = self.gui_state.follow_tail;
+        let glyph = if self.gui_state.newest_first {
+            icon::ARROW_LINE_UP
+        } else {
+            icon::ARROW_LINE_DOWN
+        };
+        let colour = if following {
+            Color32::LIGHT_BLUE
+        } else {
+            Color32::WHITE
+        };
+        if ui
+            .button(RichText::new(glyph).size(24.0).color(colour))
+            .on_hover_text(if following {
+                "Stop following newest messages"
+            } else {
+                "Jump to and follow newest messages"
+            })
+            .clicked()
+        {
+            self.gui_state.follow_tail = !following;
+            if !following {
+                self.gui_state.scroll_to = Some(if self.gui_state.newest_first {
+                    ScrollTarget::Top
+                } else {
+                    ScrollTarget::Bottom
+                });
+            }
         }
     }
 
@@ -74,41 +146,350 @@ impl super::App {
         }
     }
 
+    /// Dropdown of recently applied filters.
+    fn filter_history_menu(&mut self, ui: &mut Ui) {
+        let mut apply = None;
+        ui.menu_button(
+            RichText::new(icon::CLOCK_COUNTER_CLOCKWISE).size(24.0),
+            |ui| {
+                if self.gui_state.filter_state.history.is_empty() {
+                    ui.label(RichText::new("No recent filters").size(16.0));
+                }
+                for filter in &self.gui_state.filter_state.history {
+                    if ui.button(RichText::new(filter).size(16.0)).clicked() {
+                        apply = Some(filter.clone());
+                        ui.close_menu();
+                    }
+                }
+            },
+        )
+        .response
+        .on_hover_text("Recent filters");
+        if let Some(filter) = apply {
+            self.gui_state.filter_state.filter_string = filter;
+            self.apply_filter_change();
+        }
+    }
+
+    /// Toggles governing how the filter string is interpreted.
+    fn filter_mode_toggles(&mut self, ui: &mut Ui) {
+        let filter_state = &mut self.gui_state.filter_state;
+        let mut changed = false;
+        // The other modes only make sense of a regex.
+        ui.add_enabled_ui(!filter_state.structured, |ui| {
+            changed |= ui
+                .toggle_value(
+                    &mut filter_state.case_insensitive,
+                    RichText::new(icon::TEXT_AA).size(24.0),
+                )
+                .on_hover_text("Case insensitive")
+                .changed();
+            changed |= ui
+                .toggle_value(
+                    &mut filter_state.literal,
+                    RichText::new(icon::QUOTES).size(24.0),
+                )
+                .on_hover_text("Plain text, rather than a regex")
+                .changed();
+            changed |= ui
+                .toggle_value(
+                    &mut filter_state.whole_word,
+                    RichText::new(icon::TEXT_UNDERLINE).size(24.0),
+                )
+                .on_hover_text("Whole words only")
+                .changed();
+        });
+        changed |= ui
+            .toggle_value(
+                &mut filter_state.invert,
+                RichText::new(icon::PROHIBIT).size(24.0),
+            )
+            .on_hover_text("Hide matching messages")
+            .changed();
+        if changed {
+            self.apply_filter_change();
+        }
+    }
+
+    /// Recompile the filter and apply it to every message.
+    fn apply_filter_change(&mut self) {
+        self.gui_state.filter_state.current_match = None;
+        self.gui_state.update_regex();
+        self.gui_data.filter_all(&self.gui_state);
+    }
+
     fn regex_entry(&mut self, ui: &mut Ui) {
+        self.filter_history_menu(ui);
+        let (match_count, match_position) = self.match_summary();
+        let navigation_width = if match_count > 0 { 180.0 } else { 0.0 };
         // Add a text box that fills the remaining space
-        let available_width = ui.available_width() - 36.0; // Leave space for menu icon and padding between elements
+        let available_width = ui.available_width() - 196.0 - navigation_width; // Leave space for mode toggles, menu icon and padding between elements
+        let response = ui.add_sized(
+            [available_width, 24.0],
+            egui::TextEdit::singleline(&mut self.gui_state.filter_state.filter_string)
+                .hint_text(if self.gui_state.filter_state.structured {
+                    "filter query, e.g. headers.tenant == \"acme\" && body.$.amount > 100"
+                } else if self.gui_state.filter_state.literal {
+                    "filter text"
+                } else {
+                    "filter regex"
+                })
+                .vertical_align(egui::Align::Center)
+                .font(FontId {
+                    size: 16.0,
+                    family: FontFamily::Proportional,
+                }),
+        );
+        if response.changed() {
+            self.apply_filter_change();
+        }
+        if response.lost_focus() {
+            self.gui_state.filter_state.remember();
+        }
+        self.filter_mode_toggles(ui);
+        if match_count > 0 {
+            // Enter in the filter box steps to the next match, as does F3.
+            let next = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+            self.match_navigation(ui, match_count, match_position, next);
+        }
+    }
+
+    /// The number of matches of the filter, and the position of the current match among them.
+    /// The position is only looked for again once the matches have changed.
+    fn match_summary(&mut self) -> (usize, Option<usize>) {
+        let count = self.gui_data.match_count();
+        let (revision, newest_first) = (self.gui_data.revision(), self.gui_state.newest_first);
+        let filter_state = &mut self.gui_state.filter_state;
+        let Some(current) = filter_state.current_match else {
+            return (count, None);
+        };
+        match filter_state.match_position {
+            Some((r, n, position)) if (r, n) == (revision, newest_first) => (count, position),
+            _ => {
+                let position = self
+                    .gui_data
+                    .match_positions(newest_first)
+                    .position(|m| m == current);
+                filter_state.match_position = Some((revision, newest_first, position));
+                (count, position)
+            }
+        }
+    }
+
+    /// Previous/next match buttons and a "match n of m" counter.
+    fn match_navigation(&mut self, ui: &mut Ui, count: usize, position: Option<usize>, next: bool) {
+        let (previous_key, next_key) = ui.input(|i| {
+            let f3 = i.key_pressed(egui::Key::F3);
+            (f3 && i.modifiers.shift, f3 && !i.modifiers.shift)
+        });
+        let mut target = None;
         if ui
-            .add_sized(
-                [available_width, 24.0],
-                egui::TextEdit::singleline(&mut self.gui_state.filter_state.filter_string)
-                    .hint_text("filter regex")
-                    .vertical_align(egui::Align::Center)
-                    .font(FontId {
-                        size: 16.0,
-                        family: FontFamily::Proportional,
-                    }),
-            )
-            .changed()
+            .button(RichText::new(icon::CARET_UP).size(24.0))
+            .on_hover_text("Previous match (Shift+F3)")
+            .clicked()
+            || previous_key
         {
-            self.gui_state.update_regex();
-            self.gui_data.filter_all(&self.gui_state);
+            target = Some(position.map_or(count - 1, |p| (p + count - 1) % count));
         }
+        if ui
+            .button(RichText::new(icon::CARET_DOWN).size(24.0))
+            .on_hover_text("Next match (F3)")
+            .clicked()
+            || next_key
+            || next
+        {
+            target = Some(position.map_or(0, |p| (p + 1) % count));
+        }
+        let counter = match position {
+            Some(p) => format!("{} of {}", p + 1, count),
+            None => format!("{} matches", count),
+        };
+        ui.add_sized(
+            [100.0, 24.0],
+            egui::Label::new(RichText::new(counter).size(16.0)),
+        );
+
+        if let Some(target) = target {
+            let current = self
+                .gui_data
+                .match_positions(self.gui_state.newest_first)
+                .nth(target);
+            if let Some((id, _)) = current {
+                // Reveal the match in context.
+                if let Some(item) = self.gui_data.get_mut(id) {
+                    if !item.expanded {
+                        item.toggle_expanded();
+                    }
+                }
+                self.gui_state.filter_state.current_match = current;
+                self.gui_state.filter_state.match_position = Some((
+                    self.gui_data.revision(),
+                    self.gui_state.newest_first,
+                    Some(target),
+                ));
+                self.gui_state.filter_state.scroll_to_match = true;
+                self.gui_state.follow_tail = false;
+            }
+        }
+    }
+
+    /// Submenu listing the saved filters, and allowing the current filter to be saved.
+    fn saved_filters_menu(&mut self, ui: &mut Ui) {
+        ui.menu_button(RichText::new("Saved filters").size(16.0), |ui| {
+            let filter_state = &mut self.gui_state.filter_state;
+            let mut apply = None;
+            let mut delete_index = None;
+            for (index, saved) in filter_state.saved_filters.iter().enumerate() {
+                ui.horizontal(|ui| {
+                    if ui
+                        .button(RichText::new(icon::TRASH).color(Color32::RED))
+                        .on_hover_text("Delete saved filter")
+                        .clicked()
+                    {
+                        delete_index = Some(index);
+                    }
+                    if ui
+                        .button(RichText::new(&saved.name).size(16.0))
+                        .on_hover_text(&saved.filter_string)
+                        .clicked()
+                    {
+                        apply = Some(saved.clone());
+                    }
+                });
+            }
+            if !filter_state.saved_filters.is_empty() {
+                ui.separator();
+            }
+            ui.horizontal(|ui| {
+                ui.add(
+                    egui::TextEdit::singleline(&mut filter_state.save_as)
+                        .hint_text("name")
+                        .desired_width(120.0),
+                );
+                let can_save =
+                    !filter_state.save_as.is_empty() && !filter_state.filter_string.is_empty();
+                if ui
+                    .add_enabled(can_save, Button::new(RichText::new(icon::FLOPPY_DISK)))
+                    .on_hover_text("Save current filter")
+                    .clicked()
+                {
+                    let name = std::mem::take(&mut filter_state.save_as);
+                    filter_state.saved_filters.retain(|f| f.name != name);
+                    filter_state.saved_filters.push(SavedFilter {
+                        name,
+                        filter_string: filter_state.filter_string.clone(),
+                        structured: filter_state.structured,
+                    });
+                }
+            });
+
+            if let Some(index) = delete_index {
+                filter_state.saved_filters.remove(index);
+            }
+            if let Some(saved) = apply {
+                filter_state.filter_string = saved.filter_string;
+                filter_state.structured = saved.structured;
+                self.apply_filter_change();
+                ui.close_menu();
+            }
+        });
     }
 
     fn menu_button(&mut self, ui: &mut Ui) {
         ui.menu_button(RichText::new(icon::LIST).size(24.0), |ui| {
+            let mut changed = ui
+                .checkbox(
+                    &mut self.gui_state.filter_state.filter_headers,
+                    RichText::new("Filter headers").size(16.0),
+                )
+                .changed();
+            changed |= ui
+                .checkbox(
+                    &mut self.gui_state.filter_state.filter_body,
+                    RichText::new("Filter body").size(16.0),
+                )
+                .changed();
+            changed |= ui
+                .checkbox(
+                    &mut self.gui_state.filter_state.structured,
+                    RichText::new("Structured query").size(16.0),
+                )
+                .changed();
+            if changed {
+                self.apply_filter_change();
+            }
+            if ui
+                .checkbox(
+                    &mut self.gui_state.filter_state.bookmarked_only,
+                    RichText::new("Bookmarked only").size(16.0),
+                )
+                .changed()
+            {
+                self.gui_data.filter_all(&self.gui_state);
+            }
+            self.saved_filters_menu(ui);
             ui.checkbox(
-                &mut self.gui_state.filter_state.filter_headers,
-                RichText::new("Filter headers").size(16.0),
+                &mut self.gui_state.show_subscriptions,
+                RichText::new("Subscriptions window").size(16.0),
             );
             ui.checkbox(
-                &mut self.gui_state.filter_state.filter_body,
-                RichText::new("Filter body").size(16.0),
+                &mut self.gui_state.show_subscription_sets,
+                RichText::new("Subscription sets window").size(16.0),
             );
             ui.checkbox(
-                &mut self.gui_state.show_subscriptions,
-                RichText::new("Subscriptions window").size(16.0),
+                &mut self.gui_state.show_columns,
+                RichText::new("Columns window").size(16.0),
+            );
+            ui.checkbox(
+                &mut self.gui_state.show_statistics,
+                RichText::new("Statistics window").size(16.0),
+            );
+            ui.checkbox(
+                &mut self.gui_state.show_top_talkers,
+                RichText::new("Top talkers window").size(16.0),
+            );
+            ui.checkbox(
+                &mut self.gui_state.show_alerts,
+                RichText::new("Alerts window").size(16.0),
+            );
+            ui.checkbox(
+                &mut self.gui_state.show_capture_log,
+                RichText::new("Capture log window").size(16.0),
+            );
+            ui.checkbox(
+                &mut self.gui_state.show_highlights,
+                RichText::new("Highlights window").size(16.0),
+            );
+            ui.checkbox(
+                &mut self.gui_state.newest_first,
+                RichText::new("Newest first").size(16.0),
             );
+            ui.checkbox(
+                &mut self.gui_state.pause_cancels_consumer,
+                RichText::new("Stop consuming whilst paused").size(16.0),
+            )
+            .on_hover_text(
+                "Leave messages on the broker, rather than buffering them, whilst paused",
+            );
+            ui.checkbox(
+                &mut self.gui_state.group_rpc,
+                RichText::new("Group request/reply").size(16.0),
+            );
+            ui.horizontal(|ui| {
+                ui.label(RichText::new("Reply timeout").size(16.0));
+                ui.add(
+                    egui::DragValue::new(&mut self.gui_state.rpc_timeout_secs)
+                        .range(0.1..=3600.0)
+                        .suffix(" s"),
+                );
+            });
+            if ui
+                .button(RichText::new("Export messages...").size(16.0))
+                .clicked()
+            {
+                ui.close_menu();
+                self.export_messages();
+            }
         });
     }
 
@@ -119,6 +500,8 @@ impl super::App {
                 self.connection_settings_button(ui);
                 self.connect_button(ui);
                 self.subscribe_button(ui);
+                self.pause_button(ui);
+                self.follow_button(ui);
                 self.clear_button(ui);
                 self.regex_entry(ui);
                 self.menu_button(ui);
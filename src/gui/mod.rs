@@ -1,37 +1,96 @@
-use std::rc::Rc;
-
 use eframe::egui::{self, Color32, CornerRadius};
-use lapin::{options::QueueBindOptions, types::FieldTable};
 use model::ModelItem;
 use state::ConnectionStatus;
 
-use crate::rabbit::{Binding, ConnectionManager, ConnectionUpdate};
+use crate::rabbit::{Binding, ConnectionManager, ConnectionUpdate, ExchangeType};
 mod add_subscription_window;
+mod alerts;
+mod capture_log;
+mod columns_window;
 pub mod connection_modal;
+mod diff_window;
+mod export;
+mod highlights_window;
+mod json_tree_view;
 mod menu_bar;
 mod model;
+mod query;
+mod search_index;
 mod state;
+mod statistics;
 mod status_bar;
+mod subscription_sets;
 mod subscriptions_window;
+mod top_talkers_window;
 mod tree_data_view;
 
 mod enums;
 mod prelude;
 
+/// Storage keys under which settings are persisted between sessions.
+const SAVED_FILTERS_KEY: &str = "saved_filters";
+const FILTER_HISTORY_KEY: &str = "filter_history";
+const PROJECTION_COLUMNS_KEY: &str = "projection_columns";
+const TABLE_LAYOUTS_KEY: &str = "table_layouts";
+const ALERT_RULES_KEY: &str = "alert_rules";
+const CAPTURE_LOG_KEY: &str = "capture_log";
+const HIGHLIGHT_RULES_KEY: &str = "highlight_rules";
+const SUBSCRIPTION_SETS_KEY: &str = "subscription_sets";
+
 pub struct App {
     gui_state: state::GuiState,
     gui_data: model::Model,
+    statistics: statistics::Statistics,
+    alerts: alerts::Alerts,
+    capture_log: capture_log::CaptureLog,
     connection_manager: ConnectionManager,
     queue_bindings: Vec<Binding>,
+    /// Bindings unbound from the queue for the time being, which may be bound again.
+    disabled_bindings: Vec<Binding>,
+    /// New bindings requested of the broker but not yet confirmed.
+    binding_requests: Vec<Binding>,
+    /// Ids of bindings being enabled or disabled, which move between `queue_bindings` and
+    /// `disabled_bindings` once the broker has confirmed the change.
+    toggling: Vec<uuid::Uuid>,
 }
 
 impl App {
-    pub fn new(_cc: &eframe::CreationContext<'_>, connection_manager: ConnectionManager) -> Self {
+    pub fn new(cc: &eframe::CreationContext<'_>, connection_manager: ConnectionManager) -> Self {
+        let mut gui_state = state::GuiState::default();
+        let mut alert_rules = Vec::default();
+        let mut capture_log_settings = capture_log::CaptureLogSettings::default();
+        if let Some(storage) = cc.storage {
+            gui_state.filter_state.saved_filters =
+                eframe::get_value(storage, SAVED_FILTERS_KEY).unwrap_or_default();
+            gui_state.filter_state.history =
+                eframe::get_value(storage, FILTER_HISTORY_KEY).unwrap_or_default();
+            gui_state.projection_columns =
+                eframe::get_value(storage, PROJECTION_COLUMNS_KEY).unwrap_or_default();
+            gui_state.table_layouts =
+                eframe::get_value(storage, TABLE_LAYOUTS_KEY).unwrap_or_default();
+            alert_rules = eframe::get_value(storage, ALERT_RULES_KEY).unwrap_or_default();
+            capture_log_settings = eframe::get_value(storage, CAPTURE_LOG_KEY).unwrap_or_default();
+            gui_state.highlight_rules =
+                eframe::get_value(storage, HIGHLIGHT_RULES_KEY).unwrap_or_default();
+            gui_state.subscription_sets =
+                eframe::get_value(storage, SUBSCRIPTION_SETS_KEY).unwrap_or_default();
+        }
+        for rule in gui_state.highlight_rules.iter_mut() {
+            rule.compile();
+        }
+        gui_state.restore_table_layout();
+
         Self {
-            gui_state: state::GuiState::default(),
+            gui_state,
             gui_data: model::Model::default(),
+            statistics: statistics::Statistics::default(),
+            alerts: alerts::Alerts::new(alert_rules),
+            capture_log: capture_log::CaptureLog::new(capture_log_settings),
             connection_manager,
             queue_bindings: Vec::default(),
+            disabled_bindings: Vec::default(),
+            binding_requests: Vec::default(),
+            toggling: Vec::default(),
         }
     }
 }
@@ -39,19 +98,58 @@ impl App {
 impl App {
     fn change_connection_state(&mut self, new_state: ConnectionStatus) {
         self.gui_state.connection = new_state;
+        // Changes still outstanding on disconnecting will never complete, and the queue
+        // goes with the connection; its bindings are kept as disabled, to enable again.
+        if self.gui_state.connection == ConnectionStatus::Disconnected {
+            self.toggling.clear();
+            self.binding_requests.clear();
+            self.disabled_bindings.append(&mut self.queue_bindings);
+            if let Some(params) = self
+                .gui_state
+                .add_subscription_parameters
+                .as_mut()
+                .filter(|p| p.rebinding)
+            {
+                params.rebinding = false;
+                params.notice = Some("Disconnected before the change was made".into());
+            }
+        }
+        // A fresh connection always starts consuming.
+        if self.gui_state.connection == ConnectionStatus::Connected
+            && self.gui_data.paused
+            && self.gui_state.pause_cancels_consumer
+        {
+            self.connection_manager.set_consuming(false);
+        }
         if self.gui_state.connection == ConnectionStatus::Connected
             && self.gui_state.connection_parameters.wildcard
         {
-            // TODO
-            let _ = self
-                .connection_manager
-                .tx
-                .send(crate::rabbit::ConnectionCommand::Bind {
-                    exchange: self.gui_state.connection_parameters.exchange.clone().into(),
-                    routing_key: "".into(),
-                    options: QueueBindOptions::default(),
-                    arguments: FieldTable::default(),
-                });
+            // Wildcards from before a reconnection are among the disabled bindings, and are
+            // enabled again rather than bound a second time.
+            let exchanges = self
+                .gui_state
+                .connection_parameters
+                .wildcard_exchanges
+                .clone();
+            for exchange in exchanges {
+                if exchange.exchange_type == ExchangeType::Direct {
+                    log::warn!(
+                        "Wildcard on direct exchange '{}' only catches an empty routing key",
+                        exchange.name
+                    );
+                }
+                self.subscribe(Binding::wildcard(&exchange.name, exchange.exchange_type));
+            }
+        }
+        if self.gui_state.connection == ConnectionStatus::Connected {
+            if let Some(name) = self
+                .gui_state
+                .connection_parameters
+                .subscription_set
+                .clone()
+            {
+                self.load_subscription_set(&name);
+            }
         }
     }
     fn process_connection_update(&mut self, update: ConnectionUpdate) {
@@ -66,54 +164,113 @@ impl App {
                 self.change_connection_state(ConnectionStatus::Connecting)
             }
             ConnectionUpdate::Bound(binding) => {
+                // Enabling a disabled binding binds it again under the same id.
+                self.toggling.retain(|id| *id != binding.id);
+                self.binding_requests.retain(|b| b.id != binding.id);
+                self.disabled_bindings.retain(|b| b.id != binding.id);
                 self.queue_bindings.push(binding);
             }
+            ConnectionUpdate::Rebound(binding) => self.rebound(binding),
+            ConnectionUpdate::RebindFailed(binding) => self.rebind_failed(binding),
             ConnectionUpdate::Unbound(binding) => {
                 self.queue_bindings.retain(|b| b.id != binding.id);
+                if let Some(index) = self.toggling.iter().position(|id| *id == binding.id) {
+                    self.toggling.remove(index);
+                    self.disabled_bindings.push(binding);
+                }
             }
             ConnectionUpdate::TextDelivery {
                 headers,
                 content,
-                content_type,
+                info,
             } => {
-                let mut item = ModelItem {
-                    timestamp: chrono::Local::now()
-                        .format("%m/%d %H:%M:%S%.3f")
-                        .to_string(),
-                    headers: Rc::new(headers),
-                    body: Rc::new(content),
-                    expanded: false,
-                    highlights: Vec::default(),
-                };
+                self.statistics.record(&info);
+                let mut item = ModelItem::new(headers, content, info);
                 item.apply_filter(&self.gui_state);
+                item.project(&self.gui_state.projection_columns);
+                item.apply_highlight_rules(&self.gui_state.highlight_rules);
+                self.alerts.evaluate(&item);
+                self.capture_log
+                    .write(&item, &self.gui_state.projection_columns);
                 // TODO check if max length is hit, in which case we must pop from the front first.
-                self.gui_data.data.push_back(item);
+                self.gui_data.push(item, self.gui_state.sort);
             }
-            ConnectionUpdate::BinaryDelivery {
-                headers,
-                content_type,
-            } => {
-                let mut item = ModelItem {
-                    timestamp: chrono::Local::now()
-                        .format("%m/%d %H:%M:%S%.3f")
-                        .to_string(),
-                    headers: Rc::new(headers),
-                    body: Rc::new("-Binary data-".into()),
-                    expanded: false,
-                    highlights: Vec::default(),
-                };
+            ConnectionUpdate::BinaryDelivery { headers, info } => {
+                self.statistics.record(&info);
+                let mut item = ModelItem::new(headers, "-Binary data-".into(), info);
                 item.apply_filter(&self.gui_state);
+                item.project(&self.gui_state.projection_columns);
+                item.apply_highlight_rules(&self.gui_state.highlight_rules);
+                self.alerts.evaluate(&item);
+                self.capture_log
+                    .write(&item, &self.gui_state.projection_columns);
                 // TODO check if max length is hit, in which case we must pop from the front first.
-                self.gui_data.data.push_back(item);
+                self.gui_data.push(item, self.gui_state.sort);
             }
         }
     }
 }
 
 impl eframe::App for App {
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(
+            storage,
+            SAVED_FILTERS_KEY,
+            &self.gui_state.filter_state.saved_filters,
+        );
+        eframe::set_value(
+            storage,
+            FILTER_HISTORY_KEY,
+            &self.gui_state.filter_state.history,
+        );
+        eframe::set_value(
+            storage,
+            PROJECTION_COLUMNS_KEY,
+            &self.gui_state.projection_columns,
+        );
+        self.gui_state.store_table_layout();
+        eframe::set_value(storage, TABLE_LAYOUTS_KEY, &self.gui_state.table_layouts);
+        eframe::set_value(storage, ALERT_RULES_KEY, &self.alerts.rules);
+        eframe::set_value(storage, CAPTURE_LOG_KEY, &self.capture_log.settings);
+        eframe::set_value(
+            storage,
+            HIGHLIGHT_RULES_KEY,
+            &self.gui_state.highlight_rules,
+        );
+        eframe::set_value(
+            storage,
+            SUBSCRIPTION_SETS_KEY,
+            &self.gui_state.subscription_sets,
+        );
+    }
+
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        let mut updated = false;
         while let Some(update) = self.connection_manager.rx.try_recv().ok() {
             self.process_connection_update(update);
+            updated = true;
+        }
+        if updated {
+            self.capture_log.flush();
+        }
+        self.gui_data.receive_search_results(&self.gui_state);
+        if self.gui_data.searching() {
+            ctx.request_repaint_after(std::time::Duration::from_millis(50));
+        }
+        if self.alerts.attention_needed {
+            self.alerts.attention_needed = false;
+            ctx.send_viewport_cmd(egui::ViewportCommand::RequestUserAttention(
+                egui::UserAttentionType::Critical,
+            ));
+        }
+        // Keep checking whether outstanding requests have timed out, even when idle.
+        self.gui_data
+            .expire_requests(self.gui_state.rpc_timeout_secs);
+        if self
+            .gui_data
+            .has_pending_requests(self.gui_state.rpc_timeout_secs)
+        {
+            ctx.request_repaint_after(std::time::Duration::from_secs(1));
         }
 
         egui::CentralPanel::default()
@@ -126,6 +283,14 @@ impl eframe::App for App {
                 });
                 self.add_subscription_window(ctx);
                 self.subscriptions_window(ctx);
+                self.columns_window(ctx);
+                self.statistics_window(ctx);
+                self.top_talkers_window(ctx);
+                self.diff_window(ctx);
+                self.alerts_window(ctx);
+                self.capture_log_window(ctx);
+                self.highlights_window(ctx);
+                self.subscription_sets_window(ctx);
                 self.show_connection_modal(ctx);
 
                 self.menu_bar(ctx);
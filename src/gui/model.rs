@@ -1,11 +1,43 @@
-use std::{collections::VecDeque, rc::Rc};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap, VecDeque},
+    sync::Arc,
+};
 
-use super::state::GuiState;
+use crate::rabbit::DeliveryInfo;
+
+use super::query::{parse_path, walk};
+use super::search_index::SearchIndex;
+use super::state::{
+    DataColumn, GuiState, HighlightRule, ProjectionColumn, ProjectionSource, SortOrder,
+};
 
 pub const DEFAULT_DATA_LIMIT: usize = 1000;
+/// Requests are paired with replies arriving up to this long (or the reply timeout, if
+/// longer) afterwards; beyond that they are forgotten.
+const REQUEST_EXPIRY_SECS: f32 = 600.0;
+/// Initial guess at the height of an expanded item, until it has been drawn.
+const DEFAULT_EXPANDED_HEIGHT: f32 = 200.0;
 pub struct Model {
     data_limit: usize,
+    /// Sequence number for the next item to arrive.
+    next_id: u64,
+    /// Requests (i.e. messages with a reply_to) awaiting a reply, by correlation id, with
+    /// the time each arrived.
+    pending_requests: HashMap<String, (u64, chrono::DateTime<chrono::Local>)>,
     pub data: VecDeque<ModelItem>,
+    /// The position in `data` of each item, by id.
+    positions: HashMap<u64, usize>,
+    /// Whilst paused, arriving items are held back rather than added to `data`.
+    pub paused: bool,
+    held: Vec<ModelItem>,
+    /// Number of matches of the filter among the items in `data`.
+    match_count: usize,
+    /// Changes whenever matches may have been added, removed or reordered, so that a
+    /// position among them can be kept until it does.
+    revision: u64,
+    /// Searches the headers and bodies of all items (as they were on arrival) by regex.
+    index: SearchIndex,
 }
 
 impl Default for Model {
@@ -15,19 +47,319 @@ impl Default for Model {
         Self {
             data,
             data_limit: DEFAULT_DATA_LIMIT,
+            next_id: 0,
+            pending_requests: HashMap::default(),
+            positions: HashMap::default(),
+            paused: false,
+            held: Vec::default(),
+            match_count: 0,
+            revision: 0,
+            index: SearchIndex::default(),
         }
     }
 }
 
 impl Model {
+    /// Apply the filter to every item. Regex filters are run in the background, with items
+    /// hidden until found to match; see `receive_search_results`.
     pub fn filter_all(&mut self, gui_state: &GuiState) {
-        for item in &mut self.data {
-            item.apply_filter(gui_state);
+        let filter = &gui_state.filter_state;
+        match &filter.regex {
+            Some(regex) if !filter.structured => {
+                self.index
+                    .search(regex, filter.filter_headers, filter.filter_body);
+                // When inverted, matches are hidden as found, rather than revealed.
+                for item in self.data.iter_mut().chain(self.held.iter_mut()) {
+                    item.highlights.clear();
+                    item.matches_filter =
+                        filter.invert && (!filter.bookmarked_only || item.bookmarked);
+                }
+            }
+            _ => {
+                self.index.cancel();
+                for item in self.data.iter_mut().chain(self.held.iter_mut()) {
+                    item.apply_filter(gui_state);
+                }
+            }
+        }
+        self.match_count = self.data.iter().map(ModelItem::match_count).sum();
+        self.revision += 1;
+    }
+
+    /// Reveal (or if inverted, hide) the items found to match since last called.
+    pub fn receive_search_results(&mut self, gui_state: &GuiState) {
+        let mut results: HashMap<u64, Vec<Highlight>> = self.index.results().into_iter().collect();
+        if results.is_empty() {
+            return;
+        }
+        let shown_count = self.data.len();
+        for (index, item) in self.data.iter_mut().chain(self.held.iter_mut()).enumerate() {
+            if let Some(highlights) = results.remove(&item.id) {
+                let shown = index < shown_count;
+                if shown {
+                    self.match_count -= item.match_count();
+                }
+                if gui_state.filter_state.invert {
+                    item.matches_filter = false;
+                } else {
+                    item.highlights = highlights;
+                    item.matches_filter =
+                        !gui_state.filter_state.bookmarked_only || item.bookmarked;
+                }
+                if shown {
+                    self.match_count += item.match_count();
+                }
+            }
+        }
+        self.revision += 1;
+    }
+
+    /// Number of matches of the filter among the items shown.
+    pub fn match_count(&self) -> usize {
+        self.match_count
+    }
+
+    /// See `Model::revision`.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// Bookmark the item, or remove its bookmark, refiltering it.
+    pub fn toggle_bookmark(&mut self, id: u64, gui_state: &GuiState) {
+        let Some(index) = self.positions.get(&id).copied() else {
+            return;
+        };
+        let item = &mut self.data[index];
+        self.match_count -= item.match_count();
+        item.bookmarked = !item.bookmarked;
+        item.apply_filter(gui_state);
+        self.match_count += item.match_count();
+        self.revision += 1;
+    }
+
+    /// Each match of the filter, in display order, as the id of the item containing it and
+    /// the index of the match among that item's highlights.
+    pub fn match_positions(&self, newest_first: bool) -> impl Iterator<Item = (u64, usize)> + '_ {
+        let items: Box<dyn Iterator<Item = &ModelItem>> = if newest_first {
+            Box::new(self.data.iter().rev())
+        } else {
+            Box::new(self.data.iter())
+        };
+        items
+            .filter(|item| item.matches_filter)
+            .flat_map(|item| (0..item.highlights.len()).map(move |index| (item.id, index)))
+    }
+
+    /// Whether a filter is still being applied in the background.
+    pub fn searching(&self) -> bool {
+        self.index.searching
+    }
+
+    pub fn highlight_all(&mut self, rules: &[HighlightRule]) {
+        for item in self.data.iter_mut().chain(self.held.iter_mut()) {
+            item.apply_highlight_rules(rules);
+        }
+    }
+
+    pub fn project_all(&mut self, columns: &[ProjectionColumn]) {
+        for item in self.data.iter_mut().chain(self.held.iter_mut()) {
+            item.project(columns);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.index.clear();
+        self.data.clear();
+        self.positions.clear();
+        self.held.clear();
+        self.pending_requests.clear();
+        self.match_count = 0;
+        self.revision += 1;
+    }
+
+    /// Number of items that have ever arrived, which is also the id of the next to arrive.
+    pub fn arrivals(&self) -> u64 {
+        self.next_id
+    }
+
+    /// Number of items that arrived whilst paused.
+    pub fn held_count(&self) -> usize {
+        self.held.len()
+    }
+
+    /// Unpause, merging in everything that arrived in the meantime.
+    pub fn resume(&mut self, sort: Option<SortOrder>) {
+        self.paused = false;
+        for item in std::mem::take(&mut self.held) {
+            self.insert(item, sort);
+        }
+    }
+
+    /// The number of items (including any held) with each routing key, of those published
+    /// to `exchange`.
+    pub fn routing_key_counts(&self, exchange: &str) -> BTreeMap<&str, usize> {
+        let mut counts = BTreeMap::new();
+        for item in self.data.iter().chain(self.held.iter()) {
+            if item.info.exchange == exchange {
+                *counts.entry(item.info.routing_key.as_str()).or_default() += 1;
+            }
         }
+        counts
+    }
+
+    /// Whether any request awaiting a reply has yet to exceed the timeout (in seconds).
+    pub fn has_pending_requests(&self, timeout: f32) -> bool {
+        let now = chrono::Local::now();
+        self.pending_requests
+            .values()
+            .any(|(_, received)| (now - *received).as_seconds_f32() <= timeout)
+    }
+
+    /// Stop waiting for replies to requests long past the timeout (in seconds).
+    pub fn expire_requests(&mut self, timeout: f32) {
+        let now = chrono::Local::now();
+        let expiry = timeout.max(REQUEST_EXPIRY_SECS);
+        self.pending_requests
+            .retain(|_, (_, received)| (now - *received).as_seconds_f32() <= expiry);
+    }
+
+    pub fn get(&self, id: u64) -> Option<&ModelItem> {
+        match self.positions.get(&id) {
+            Some(index) => Some(&self.data[*index]),
+            None => self.held.iter().find(|item| item.id == id),
+        }
+    }
+
+    /// As `get`, but only among the items in `data`, i.e. not those held whilst paused.
+    pub fn get_listed(&self, id: u64) -> Option<&ModelItem> {
+        self.positions.get(&id).map(|index| &self.data[*index])
+    }
+
+    pub fn get_mut(&mut self, id: u64) -> Option<&mut ModelItem> {
+        match self.positions.get(&id) {
+            Some(index) => Some(&mut self.data[*index]),
+            None => self.held.iter_mut().find(|item| item.id == id),
+        }
+    }
+
+    /// Record the positions of the items in `data` from `start` onwards.
+    fn reindex(&mut self, start: usize) {
+        for (index, item) in self.data.iter().enumerate().skip(start) {
+            self.positions.insert(item.id, index);
+        }
+    }
+
+    /// Pair up RPC requests and replies by correlation id.
+    fn correlate(&mut self, item: &mut ModelItem) {
+        let Some(correlation_id) = &item.info.correlation_id else {
+            return;
+        };
+        if item.info.reply_to.is_some() {
+            self.pending_requests
+                .insert(correlation_id.clone(), (item.id, item.received));
+            item.rpc = Some(Rpc::Request { reply: None });
+        } else if let Some((request_id, _)) = self.pending_requests.remove(correlation_id) {
+            if let Some(request) = self.get_mut(request_id) {
+                let latency = item.received - request.received;
+                request.rpc = Some(Rpc::Request {
+                    reply: Some((item.id, latency)),
+                });
+                item.rpc = Some(Rpc::Reply {
+                    request: request_id,
+                    latency,
+                });
+            }
+        }
+    }
+
+    /// Add a newly arrived item, at the position dictated by the sort order (if any).
+    pub fn push(&mut self, mut item: ModelItem, sort: Option<SortOrder>) {
+        item.id = self.next_id;
+        self.next_id += 1;
+        self.correlate(&mut item);
+        self.index.add(&item);
+        if self.paused {
+            self.held.push(item);
+        } else {
+            self.insert(item, sort);
+        }
+    }
+
+    fn insert(&mut self, item: ModelItem, sort: Option<SortOrder>) {
+        // Items without matches don't move any others among the matches.
+        if item.match_count() > 0 {
+            self.match_count += item.match_count();
+            self.revision += 1;
+        }
+        match sort {
+            None => {
+                self.positions.insert(item.id, self.data.len());
+                self.data.push_back(item);
+            }
+            Some(order) => {
+                let index = self
+                    .data
+                    .partition_point(|other| order.compare(other, &item) != Ordering::Greater);
+                self.data.insert(index, item);
+                self.reindex(index);
+            }
+        }
+    }
+
+    pub fn sort(&mut self, sort: Option<SortOrder>) {
+        let order = sort.unwrap_or(SortOrder {
+            column: DataColumn::Timestamp,
+            ascending: true,
+        });
+        self.data
+            .make_contiguous()
+            .sort_by(|a, b| order.compare(a, b));
+        self.reindex(0);
+        self.revision += 1;
     }
 }
 
-#[derive(PartialEq, Eq)]
+impl SortOrder {
+    pub fn compare(&self, a: &ModelItem, b: &ModelItem) -> Ordering {
+        let ordering = match self.column {
+            DataColumn::Timestamp => a.id.cmp(&b.id),
+            DataColumn::Exchange => a.info.exchange.cmp(&b.info.exchange),
+            DataColumn::RoutingKey => a.info.routing_key.cmp(&b.info.routing_key),
+            DataColumn::ContentType => a.info.content_type.cmp(&b.info.content_type),
+            DataColumn::Size => a.info.size.cmp(&b.info.size),
+            DataColumn::MessageId => a.info.message_id.cmp(&b.info.message_id),
+            DataColumn::Headers => a.headers.cmp(&b.headers),
+            DataColumn::Body => a.body.cmp(&b.body),
+            DataColumn::Projection(index) => compare_projections(
+                a.projections.get(index).map(String::as_str),
+                b.projections.get(index).map(String::as_str),
+            ),
+        };
+        if self.ascending {
+            ordering
+        } else {
+            ordering.reverse()
+        }
+    }
+}
+
+/// Order projection values numerically where they are numbers, with all numbers before
+/// all other values, which are ordered as strings. Missing values sort as empty strings.
+fn compare_projections(a: Option<&str>, b: Option<&str>) -> Ordering {
+    fn key(value: Option<&str>) -> (Option<f64>, &str) {
+        let value = value.unwrap_or_default();
+        (value.parse::<f64>().ok(), value)
+    }
+    let ((a_number, a), (b_number, b)) = (key(a), key(b));
+    match (a_number, b_number) {
+        (Some(a_number), Some(b_number)) => a_number.total_cmp(&b_number).then_with(|| a.cmp(b)),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => a.cmp(b),
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub enum HighlightField {
     Header,
     Body,
@@ -39,45 +371,302 @@ pub struct Highlight {
     pub end: usize,
 }
 
+/// A match of a highlight rule, in that rule's colour.
+pub struct RuleHighlight {
+    pub field: HighlightField,
+    pub start: usize,
+    pub end: usize,
+    pub colour: eframe::egui::Color32,
+}
+
+/// The role of a message in a request/reply exchange, as determined by its
+/// correlation_id and reply_to properties.
+pub enum Rpc {
+    /// The id of the reply and the round trip latency, once it has arrived.
+    Request {
+        reply: Option<(u64, chrono::TimeDelta)>,
+    },
+    Reply {
+        request: u64,
+        latency: chrono::TimeDelta,
+    },
+}
+
 pub struct ModelItem {
+    /// Arrival sequence number, unique within the model.
+    pub id: u64,
+    pub received: chrono::DateTime<chrono::Local>,
     pub timestamp: String,
-    pub headers: Rc<String>,
-    pub body: Rc<String>,
+    pub headers: Arc<String>,
+    pub body: Arc<String>,
     pub expanded: bool,
     pub highlights: Vec<Highlight>,
+    /// Matches of the highlight rules, which are independent of the filter.
+    pub rule_highlights: Vec<RuleHighlight>,
+    /// Whether the item satisfies the current filter, i.e. should be displayed.
+    pub matches_filter: bool,
+    pub info: DeliveryInfo,
+    pub rpc: Option<Rpc>,
+    /// Values of the user-defined projection columns, in column order.
+    pub projections: Vec<String>,
+    /// Parsed headers and (if it is JSON) body, populated only whilst the item is expanded.
+    pub trees: Option<(serde_json::Value, Option<serde_json::Value>)>,
+    pub bookmarked: bool,
+    /// Free text annotation by the user.
+    pub note: String,
+    /// Height of the expanded detail, as measured when last drawn.
+    pub expanded_height: f32,
 }
 
 impl ModelItem {
+    pub fn new(headers: String, body: String, info: DeliveryInfo) -> Self {
+        let received = chrono::Local::now();
+        Self {
+            id: 0,
+            received,
+            timestamp: received.format("%m/%d %H:%M:%S%.3f").to_string(),
+            headers: Arc::new(headers),
+            body: Arc::new(body),
+            expanded: false,
+            highlights: Vec::default(),
+            rule_highlights: Vec::default(),
+            matches_filter: true,
+            info,
+            rpc: None,
+            projections: Vec::default(),
+            trees: None,
+            bookmarked: false,
+            note: String::default(),
+            expanded_height: DEFAULT_EXPANDED_HEIGHT,
+        }
+    }
+
+    /// Compute the value of each projection column for this item.
+    pub fn project(&mut self, columns: &[ProjectionColumn]) {
+        let mut headers: Option<serde_json::Value> = None;
+        let mut body: Option<serde_json::Value> = None;
+        self.projections = columns
+            .iter()
+            .map(|column| {
+                let (cache, text) = match column.source {
+                    ProjectionSource::Headers => (&mut headers, &self.headers),
+                    ProjectionSource::Body => (&mut body, &self.body),
+                };
+                let json =
+                    cache.get_or_insert_with(|| serde_json::from_str(text).unwrap_or_default());
+                match walk(json, &parse_path(&column.path)) {
+                    None => String::default(),
+                    Some(serde_json::Value::String(s)) => s.clone(),
+                    Some(v) => v.to_string(),
+                }
+            })
+            .collect();
+    }
+
+    pub fn apply_highlight_rules(&mut self, rules: &[HighlightRule]) {
+        self.rule_highlights.clear();
+        for rule in rules {
+            let Some(regex) = rule.regex.as_ref().filter(|_| rule.enabled) else {
+                continue;
+            };
+            for (field, text) in [
+                (HighlightField::Header, &self.headers),
+                (HighlightField::Body, &self.body),
+            ] {
+                self.rule_highlights
+                    .extend(regex.find_iter(text).map(|m| RuleHighlight {
+                        field,
+                        start: m.start(),
+                        end: m.end(),
+                        colour: rule.colour,
+                    }));
+            }
+        }
+        self.rule_highlights.shrink_to_fit();
+    }
+
+    pub fn toggle_expanded(&mut self) {
+        self.expanded = !self.expanded;
+        self.trees = if self.expanded {
+            Some((
+                serde_json::from_str(&self.headers).unwrap_or_default(),
+                serde_json::from_str(&self.body).ok(),
+            ))
+        } else {
+            None
+        };
+    }
+
+    /// Number of matches of the filter in this item, if shown.
+    fn match_count(&self) -> usize {
+        if self.matches_filter {
+            self.highlights.len()
+        } else {
+            0
+        }
+    }
+
     pub fn apply_filter(&mut self, gui_state: &GuiState) {
+        self.apply_filter_string(gui_state);
+        let filter = &gui_state.filter_state;
+        // Inverting hides what matches, so there is nothing to highlight in what remains.
+        if filter.invert && (filter.regex.is_some() || filter.query.is_some()) {
+            self.matches_filter = !self.matches_filter;
+            self.highlights.clear();
+        }
+        if filter.bookmarked_only && !self.bookmarked {
+            self.matches_filter = false;
+        }
+    }
+
+    fn apply_filter_string(&mut self, gui_state: &GuiState) {
+        if gui_state.filter_state.structured {
+            self.highlights.clear();
+            self.highlights.shrink_to_fit();
+            self.matches_filter = match &gui_state.filter_state.query {
+                None => true,
+                Some(query) => query.matches(self),
+            };
+            return;
+        }
         match &gui_state.filter_state.regex {
             None => {
                 self.highlights.clear();
                 self.highlights.shrink_to_fit();
+                self.matches_filter = true;
             }
             Some(regex) => {
                 self.highlights.clear();
+                // Highlights from every searched field coexist, headers first.
                 if gui_state.filter_state.filter_headers {
-                    let it = regex.find_iter(&self.headers).peekable();
-                    self.highlights = it
-                        .map(|m| Highlight {
+                    self.highlights
+                        .extend(regex.find_iter(&self.headers).map(|m| Highlight {
                             field: HighlightField::Header,
                             start: m.start(),
                             end: m.end(),
-                        })
-                        .collect();
+                        }));
                 }
                 if gui_state.filter_state.filter_body {
-                    let it = regex.find_iter(&self.body).peekable();
-                    self.highlights = it
-                        .map(|m| Highlight {
+                    self.highlights
+                        .extend(regex.find_iter(&self.body).map(|m| Highlight {
                             field: HighlightField::Body,
                             start: m.start(),
                             end: m.end(),
-                        })
-                        .collect();
+                        }));
                 }
                 self.highlights.shrink_to_fit();
+                self.matches_filter = !self.highlights.is_empty();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn projections_are_totally_ordered() {
+        let mut values = vec!["1a", "10", "NaN", "", "9", "-inf", "abc", "9.0", "1e1"];
+        values.sort_by(|a, b| compare_projections(Some(a), Some(b)));
+        assert_eq!(
+            values,
+            vec!["-inf", "9", "9.0", "10", "1e1", "NaN", "", "1a", "abc"]
+        );
+        for a in &values {
+            for b in &values {
+                for c in &values {
+                    let (ab, bc) = (
+                        compare_projections(Some(a), Some(b)),
+                        compare_projections(Some(b), Some(c)),
+                    );
+                    if ab == bc && ab != Ordering::Equal {
+                        assert_eq!(compare_projections(Some(a), Some(c)), ab);
+                    }
+                }
             }
         }
     }
+
+    fn item(routing_key: &str, correlation_id: &str, reply_to: Option<&str>) -> ModelItem {
+        ModelItem::new(
+            String::default(),
+            String::default(),
+            DeliveryInfo {
+                routing_key: routing_key.into(),
+                correlation_id: Some(correlation_id.into()),
+                reply_to: reply_to.map(Into::into),
+                ..Default::default()
+            },
+        )
+    }
+
+    #[test]
+    fn items_are_found_by_id_whatever_the_order() {
+        let mut model = Model::default();
+        let order = SortOrder {
+            column: DataColumn::RoutingKey,
+            ascending: true,
+        };
+        for key in ["c", "a", "b"] {
+            model.push(item(key, key, None), Some(order));
+        }
+        for (id, key) in [(0, "c"), (1, "a"), (2, "b")] {
+            assert_eq!(model.get(id).unwrap().info.routing_key, key);
+        }
+        model.sort(None);
+        assert_eq!(model.get(1).unwrap().info.routing_key, "a");
+        assert!(model.get(3).is_none());
+
+        model.paused = true;
+        model.push(item("d", "d", None), None);
+        assert_eq!(model.get(3).unwrap().info.routing_key, "d");
+        assert!(model.get_listed(3).is_none());
+        assert_eq!(model.get_mut(3).unwrap().info.routing_key, "d");
+    }
+
+    #[test]
+    fn pending_requests_expire() {
+        let mut model = Model::default();
+        model.push(item("rpc", "1", Some("reply")), None);
+        assert!(model.has_pending_requests(30.0));
+        model.pending_requests.get_mut("1").unwrap().1 -= chrono::TimeDelta::seconds(60);
+        assert!(!model.has_pending_requests(30.0));
+        model.expire_requests(30.0);
+        assert_eq!(model.pending_requests.len(), 1);
+        model.pending_requests.get_mut("1").unwrap().1 -= chrono::TimeDelta::hours(1);
+        model.expire_requests(30.0);
+        assert!(model.pending_requests.is_empty());
+    }
+
+    #[test]
+    fn matches_are_counted_as_items_arrive_and_are_bookmarked() {
+        let mut gui_state = GuiState::default();
+        gui_state.filter_state.filter_string = "a".into();
+        gui_state.filter_state.filter_body = true;
+        gui_state.update_regex();
+        let mut model = Model::default();
+        for body in ["a a", "b", "a"] {
+            let mut item = ModelItem::new(String::default(), body.into(), DeliveryInfo::default());
+            item.apply_filter(&gui_state);
+            model.push(item, None);
+        }
+        assert_eq!(model.match_count(), 3);
+
+        gui_state.filter_state.bookmarked_only = true;
+        model.filter_all(&gui_state);
+        assert_eq!(model.match_count(), 0);
+        let revision = model.revision();
+        model.toggle_bookmark(0, &gui_state);
+        assert_eq!(model.match_count(), 2);
+        assert_ne!(model.revision(), revision);
+        model.clear();
+        assert_eq!(model.match_count(), 0);
+    }
+
+    #[test]
+    fn missing_projection_sorts_as_empty() {
+        assert_eq!(compare_projections(None, Some("")), Ordering::Equal);
+        assert_eq!(compare_projections(Some("1"), None), Ordering::Less);
+    }
 }
@@ -0,0 +1,637 @@
+//! A small query language for structured filtering of messages, e.g.
+//!
+//! `headers.tenant == "acme" && routing_key ~ "order\\..*" && body.$.amount > 100`
+//!
+//! Fields are `exchange`, `routing_key`, `content_type`, `correlation_id`, `reply_to`,
+//! `timestamp`, `headers` and `body`, the last two optionally followed by a path into the
+//! (JSON) headers or body such as `.x-death[0].count`.
+//! Comparison operators are `==`, `!=`, `<`, `<=`, `>`, `>=` and `~` (regex match); a bare
+//! field is true if it is present and not null or false. Terms combine with `&&`, `||`, `!`
+//! and parentheses. Values compare numerically if both are numbers (or strings of digits),
+//! and `timestamp` is compared with a time such as `"2024-05-01 09:30:00"`, `"09:30"` (today)
+//! or a number of seconds since the unix epoch.
+
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone};
+use serde_json::Value;
+
+use super::model::ModelItem;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Match,
+}
+
+#[derive(Debug)]
+enum Literal {
+    Json(Value),
+    Regex(regex::Regex),
+    Time(DateTime<Local>),
+}
+
+#[derive(Debug)]
+enum Field {
+    Exchange,
+    RoutingKey,
+    ContentType,
+    CorrelationId,
+    ReplyTo,
+    Timestamp,
+    Headers(Vec<String>),
+    Body(Vec<String>),
+}
+
+#[derive(Debug)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Truthy(Field),
+    Compare(Field, Op, Literal),
+}
+
+#[derive(Debug)]
+pub struct Query {
+    expr: Expr,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Num(f64),
+    Op(Op),
+    And,
+    Or,
+    Not,
+    LParen,
+    RParen,
+}
+
+/// Split the input into tokens, each with its byte offset within the input.
+fn tokenize(input: &str) -> Result<Vec<(usize, Token)>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+
+    while let Some(&(pos, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        let two: String = input[pos..].chars().take(2).collect();
+        let (token, len) = match two.as_str() {
+            "&&" => (Token::And, 2),
+            "||" => (Token::Or, 2),
+            "==" => (Token::Op(Op::Eq), 2),
+            "!=" => (Token::Op(Op::Ne), 2),
+            "<=" => (Token::Op(Op::Le), 2),
+            ">=" => (Token::Op(Op::Ge), 2),
+            _ => match c {
+                '<' => (Token::Op(Op::Lt), 1),
+                '>' => (Token::Op(Op::Gt), 1),
+                '~' => (Token::Op(Op::Match), 1),
+                '!' => (Token::Not, 1),
+                '(' => (Token::LParen, 1),
+                ')' => (Token::RParen, 1),
+                '"' => {
+                    chars.next();
+                    let mut s = String::new();
+                    loop {
+                        match chars.next() {
+                            None => return Err(format!("Unterminated string at {}", pos)),
+                            Some((_, '"')) => break,
+                            Some((_, '\\')) => match chars.next() {
+                                Some((_, escaped)) => s.push(escaped),
+                                None => return Err(format!("Unterminated string at {}", pos)),
+                            },
+                            Some((_, c)) => s.push(c),
+                        }
+                    }
+                    tokens.push((pos, Token::Str(s)));
+                    continue;
+                }
+                c if c.is_ascii_digit() || c == '-' => {
+                    let mut s = String::new();
+                    while let Some(&(_, c)) = chars.peek() {
+                        if c.is_ascii_digit() || c == '-' || c == '.' || c == 'e' || c == 'E' {
+                            s.push(c);
+                            chars.next();
+                        } else {
+                            break;
+                        }
+                    }
+                    let n = s
+                        .parse()
+                        .map_err(|_| format!("Invalid number '{}' at {}", s, pos))?;
+                    tokens.push((pos, Token::Num(n)));
+                    continue;
+                }
+                c if is_ident_char(c) => {
+                    let mut s = String::new();
+                    while let Some(&(_, c)) = chars.peek() {
+                        if is_ident_char(c) {
+                            s.push(c);
+                            chars.next();
+                        } else {
+                            break;
+                        }
+                    }
+                    tokens.push((pos, Token::Ident(s)));
+                    continue;
+                }
+                c => return Err(format!("Unexpected '{}' at {}", c, pos)),
+            },
+        };
+        tokens.push((pos, token));
+        for _ in 0..len {
+            chars.next();
+        }
+    }
+    Ok(tokens)
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | '$' | '[' | ']')
+}
+
+/// Split a JSONPath such as `$.x-death[0].count` into its segments.
+pub(crate) fn parse_path(path: &str) -> Vec<String> {
+    path.split(['.', '['])
+        .map(|s| s.trim_end_matches(']'))
+        .filter(|s| !s.is_empty() && *s != "$")
+        .map(String::from)
+        .collect()
+}
+
+/// Split e.g. `headers.$.x-death[0].count` into its root (`headers`) and path.
+fn parse_field(ident: &str) -> Result<Field, String> {
+    let mut path = parse_path(ident);
+    let root = if path.is_empty() {
+        String::default()
+    } else {
+        path.remove(0)
+    };
+
+    match (root.as_str(), path.is_empty()) {
+        ("exchange", true) => Ok(Field::Exchange),
+        ("routing_key", true) => Ok(Field::RoutingKey),
+        ("content_type", true) => Ok(Field::ContentType),
+        ("correlation_id", true) => Ok(Field::CorrelationId),
+        ("reply_to", true) => Ok(Field::ReplyTo),
+        ("timestamp", true) => Ok(Field::Timestamp),
+        ("headers", _) => Ok(Field::Headers(path)),
+        ("body", _) => Ok(Field::Body(path)),
+        _ => Err(format!("Unknown field '{}'", ident)),
+    }
+}
+
+/// Parse a time as accepted for comparison with `timestamp`.
+fn parse_time(s: &str) -> Option<DateTime<Local>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+        return Some(time.with_timezone(&Local));
+    }
+    let naive = [
+        "%Y-%m-%d %H:%M:%S%.f",
+        "%Y-%m-%dT%H:%M:%S%.f",
+        "%Y-%m-%d %H:%M",
+    ]
+    .iter()
+    .find_map(|format| NaiveDateTime::parse_from_str(s, format).ok())
+    .or_else(|| {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d")
+            .ok()
+            .map(|date| date.and_time(NaiveTime::MIN))
+    })
+    .or_else(|| {
+        ["%H:%M:%S%.f", "%H:%M"]
+            .iter()
+            .find_map(|format| NaiveTime::parse_from_str(s, format).ok())
+            .map(|time| Local::now().date_naive().and_time(time))
+    })?;
+    Local.from_local_datetime(&naive).earliest()
+}
+
+struct Parser<'a> {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    input: &'a str,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, token)| token)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        self.pos += 1;
+        token
+    }
+
+    /// Describe where the next token is, for error messages.
+    fn position(&self) -> String {
+        match self.tokens.get(self.pos) {
+            Some((offset, _)) => format!("at {}", offset),
+            None => format!("at end ({})", self.input.len()),
+        }
+    }
+
+    /// The token at `index` as the user typed it, for error messages. Tokens
+    /// are only separated by whitespace, so it runs up to the next one.
+    fn typed(&self, index: usize) -> Option<&str> {
+        let (start, _) = self.tokens.get(index)?;
+        let end = self
+            .tokens
+            .get(index + 1)
+            .map_or(self.input.len(), |(next, _)| *next);
+        Some(self.input[*start..end].trim_end())
+    }
+
+    /// Name the token at `index` after an "Expected ..." message, or nothing
+    /// at the end of the input where the position already says so.
+    fn found(&self, index: usize) -> String {
+        self.typed(index)
+            .map(|typed| format!(", found '{}'", typed))
+            .unwrap_or_default()
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            lhs = Expr::Or(Box::new(lhs), Box::new(self.and()?));
+        }
+        Ok(lhs)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.next();
+            lhs = Expr::And(Box::new(lhs), Box::new(self.unary()?));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        let index = self.pos;
+        let position = self.position();
+        match self.next() {
+            Some(Token::Not) => Ok(Expr::Not(Box::new(self.unary()?))),
+            Some(Token::LParen) => {
+                let expr = self.or()?;
+                let index = self.pos;
+                let position = self.position();
+                match self.next() {
+                    Some(Token::RParen) => Ok(expr),
+                    _ => Err(format!("Expected ')' {}{}", position, self.found(index))),
+                }
+            }
+            Some(Token::Ident(ident)) => {
+                let field = parse_field(&ident).map_err(|e| format!("{} {}", e, position))?;
+                let op = match self.peek() {
+                    Some(Token::Op(op)) => *op,
+                    _ => return Ok(Expr::Truthy(field)),
+                };
+                self.next();
+                let index = self.pos;
+                let position = self.position();
+                let literal = match (op, self.next()) {
+                    (Op::Match, Some(Token::Str(s))) => Literal::Regex(
+                        regex::Regex::new(&s).map_err(|e| format!("{} {}", e, position))?,
+                    ),
+                    (Op::Match, _) => {
+                        return Err(format!("Expected a regex string after '~' {}", position))
+                    }
+                    (_, Some(Token::Str(s))) if matches!(field, Field::Timestamp) => Literal::Time(
+                        parse_time(&s)
+                            .ok_or_else(|| format!("Invalid time '{}' {}", s, position))?,
+                    ),
+                    (_, Some(Token::Num(n))) if matches!(field, Field::Timestamp) => Literal::Time(
+                        DateTime::from_timestamp_millis((n * 1000.0) as i64)
+                            .ok_or_else(|| format!("Invalid time {} {}", n, position))?
+                            .with_timezone(&Local),
+                    ),
+                    (_, _) if matches!(field, Field::Timestamp) => {
+                        return Err(format!("Expected a time {}", position))
+                    }
+                    (_, Some(Token::Str(s))) => Literal::Json(Value::String(s)),
+                    (_, Some(Token::Num(n))) => Literal::Json(serde_json::json!(n)),
+                    (_, Some(Token::Ident(i))) if i == "true" => Literal::Json(Value::Bool(true)),
+                    (_, Some(Token::Ident(i))) if i == "false" => Literal::Json(Value::Bool(false)),
+                    (_, Some(Token::Ident(i))) if i == "null" => Literal::Json(Value::Null),
+                    (_, _) => {
+                        return Err(format!(
+                            "Expected a value {}{}",
+                            position,
+                            self.found(index)
+                        ))
+                    }
+                };
+                Ok(Expr::Compare(field, op, literal))
+            }
+            _ => Err(format!(
+                "Expected a field {}{}",
+                position,
+                self.found(index)
+            )),
+        }
+    }
+}
+
+impl Query {
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let mut parser = Parser {
+            tokens: tokenize(input)?,
+            pos: 0,
+            input,
+        };
+        let expr = parser.or()?;
+        match parser.peek() {
+            None => Ok(Self { expr }),
+            Some(_) => Err(format!(
+                "Unexpected '{}' {}",
+                parser.typed(parser.pos).unwrap_or_default(),
+                parser.position()
+            )),
+        }
+    }
+
+    pub fn matches(&self, item: &ModelItem) -> bool {
+        let mut context = EvalContext {
+            item,
+            headers: None,
+            body: None,
+        };
+        context.eval(&self.expr)
+    }
+}
+
+/// Lazily parses headers and body so that they are only parsed if, and at most once, needed.
+struct EvalContext<'a> {
+    item: &'a ModelItem,
+    headers: Option<Value>,
+    body: Option<Value>,
+}
+
+pub(crate) fn walk<'v>(mut value: &'v Value, path: &[String]) -> Option<&'v Value> {
+    for segment in path {
+        value = match value {
+            Value::Object(map) => map.get(segment)?,
+            Value::Array(arr) => arr.get(segment.parse::<usize>().ok()?)?,
+            _ => return None,
+        };
+    }
+    Some(value)
+}
+
+/// Numbers, and strings of (optionally signed, optionally fractional) decimal digits, as
+/// numbers; not e.g. "nan", "inf" or "1e5", which are taken to be text.
+fn as_number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => {
+            let unsigned = s.strip_prefix('-').unwrap_or(s);
+            let (whole, fraction) = unsigned.split_once('.').unwrap_or((unsigned, "0"));
+            let digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+            if digits(whole) && digits(fraction) {
+                s.parse().ok()
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+fn compare_ordering(op: Op, ordering: Option<std::cmp::Ordering>) -> bool {
+    match (op, ordering) {
+        (Op::Ne, None) => true,
+        (_, None) => false,
+        (Op::Eq, Some(o)) => o.is_eq(),
+        (Op::Ne, Some(o)) => o.is_ne(),
+        (Op::Lt, Some(o)) => o.is_lt(),
+        (Op::Le, Some(o)) => o.is_le(),
+        (Op::Gt, Some(o)) => o.is_gt(),
+        (Op::Ge, Some(o)) => o.is_ge(),
+        (Op::Match, _) => false,
+    }
+}
+
+fn compare(value: &Value, op: Op, literal: &Literal) -> bool {
+    match literal {
+        Literal::Regex(regex) => match value {
+            Value::String(s) => regex.is_match(s),
+            v => regex.is_match(&v.to_string()),
+        },
+        Literal::Json(literal) => {
+            let ordering = match (as_number(value), as_number(literal)) {
+                (Some(a), Some(b)) => a.partial_cmp(&b),
+                _ => match (value, literal) {
+                    (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+                    (a, b) if a == b => Some(std::cmp::Ordering::Equal),
+                    _ => None,
+                },
+            };
+            compare_ordering(op, ordering)
+        }
+        // Times are compared by `EvalContext::eval`, against the time of arrival.
+        Literal::Time(_) => false,
+    }
+}
+
+impl EvalContext<'_> {
+    fn resolve(&mut self, field: &Field) -> Option<Value> {
+        let item = self.item;
+        match field {
+            Field::Exchange => Some(Value::String(item.info.exchange.clone())),
+            Field::RoutingKey => Some(Value::String(item.info.routing_key.clone())),
+            Field::ContentType => item.info.content_type.clone().map(Value::String),
+            Field::CorrelationId => item.info.correlation_id.clone().map(Value::String),
+            Field::ReplyTo => item.info.reply_to.clone().map(Value::String),
+            Field::Timestamp => Some(Value::String(item.timestamp.clone())),
+            Field::Headers(path) => {
+                let headers = self
+                    .headers
+                    .get_or_insert_with(|| serde_json::from_str(&item.headers).unwrap_or_default());
+                walk(headers, path).cloned()
+            }
+            Field::Body(path) if path.is_empty() => Some(Value::String(item.body.to_string())),
+            Field::Body(path) => {
+                let body = self
+                    .body
+                    .get_or_insert_with(|| serde_json::from_str(&item.body).unwrap_or_default());
+                walk(body, path).cloned()
+            }
+        }
+    }
+
+    fn eval(&mut self, expr: &Expr) -> bool {
+        match expr {
+            Expr::And(a, b) => self.eval(a) && self.eval(b),
+            Expr::Or(a, b) => self.eval(a) || self.eval(b),
+            Expr::Not(a) => !self.eval(a),
+            Expr::Truthy(field) => !matches!(
+                self.resolve(field),
+                None | Some(Value::Null) | Some(Value::Bool(false))
+            ),
+            Expr::Compare(Field::Timestamp, op, Literal::Time(time)) => {
+                compare_ordering(*op, Some(self.item.received.cmp(time)))
+            }
+            Expr::Compare(field, op, literal) => match self.resolve(field) {
+                Some(value) => compare(&value, *op, literal),
+                None => *op == Op::Ne,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rabbit::DeliveryInfo;
+
+    fn item(headers: &str, body: &str) -> ModelItem {
+        ModelItem::new(
+            headers.into(),
+            body.into(),
+            DeliveryInfo {
+                exchange: "events".into(),
+                routing_key: "order.created".into(),
+                ..Default::default()
+            },
+        )
+    }
+
+    fn matches(query: &str, item: &ModelItem) -> bool {
+        Query::parse(query)
+            .unwrap_or_else(|e| panic!("{}: {}", query, e))
+            .matches(item)
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let item = item("{}", "");
+        // Parsed as `a || (b && c)`, not `(a || b) && c`.
+        assert!(matches(
+            r#"exchange == "events" || routing_key == "x" && exchange == "x""#,
+            &item
+        ));
+        assert!(!matches(
+            r#"(exchange == "events" || routing_key == "x") && exchange == "x""#,
+            &item
+        ));
+        assert!(matches(
+            r#"routing_key == "x" && exchange == "x" || exchange == "events""#,
+            &item
+        ));
+        assert!(!matches(r#"!exchange == "events" || !exchange"#, &item));
+        assert!(matches(r#"!(exchange == "x" && exchange)"#, &item));
+    }
+
+    #[test]
+    fn strings_may_contain_escaped_quotes_and_backslashes() {
+        let item = item(r#"{"q": "say \"hi\" \\ ok"}"#, "");
+        assert!(matches(r#"headers.q == "say \"hi\" \\ ok""#, &item));
+        assert!(!matches(r#"headers.q == "say \"hi\" ok""#, &item));
+    }
+
+    #[test]
+    fn tilde_matches_a_regex() {
+        let item = item(r#"{"count": 42}"#, "");
+        assert!(matches(r#"routing_key ~ "^order\\.c""#, &item));
+        assert!(!matches(r#"routing_key ~ "^orders""#, &item));
+        // Non-string values are matched by their JSON text.
+        assert!(matches(r#"headers.count ~ "^4\\d$""#, &item));
+        assert!(Query::parse(r#"routing_key ~ 1"#).is_err());
+        assert!(Query::parse(r#"routing_key ~ "(""#).is_err());
+    }
+
+    #[test]
+    fn body_paths_into_missing_fields() {
+        let item = item("{}", r#"{"order": {"lines": [{"amount": 5}]}}"#);
+        assert!(matches("body.$.order.lines[0].amount == 5", &item));
+        assert!(matches("body.$.order.lines[0].amount > 4.5", &item));
+        assert!(!matches("body.$.order.lines[1].amount == 5", &item));
+        assert!(matches("body.$.order.lines[1].amount != 5", &item));
+        assert!(!matches("body.$.order.missing", &item));
+        assert!(!matches("body.$.order.missing < 1", &item));
+        assert!(matches("body.$.order.lines", &item));
+        let not_json = super::tests::item("{}", "plain text");
+        assert!(!matches("body.$.order", &not_json));
+        assert!(matches(r#"body ~ "plain""#, &not_json));
+    }
+
+    #[test]
+    fn only_digit_strings_compare_as_numbers() {
+        let item = item(
+            r#"{"n": "12", "f": "-1.5", "nan": "nan", "inf": "inf", "e": "1e5"}"#,
+            "",
+        );
+        // Numerically 12 > 9, whereas "12" < "9" as text.
+        assert!(matches("headers.n > 9", &item));
+        assert!(matches("headers.f < -1", &item));
+        assert!(matches("headers.n == 12", &item));
+        for field in ["nan", "inf", "e"] {
+            for op in ["==", "<", ">"] {
+                let query = format!("headers.{} {} 1", field, op);
+                assert!(!matches(&query, &item), "{}", query);
+            }
+        }
+        assert!(matches(r#"headers.nan == "nan""#, &item));
+    }
+
+    #[test]
+    fn timestamps_compare_as_times() {
+        let mut item = item("{}", "");
+        item.received = Local.with_ymd_and_hms(2024, 12, 31, 23, 0, 0).unwrap();
+        item.timestamp = item.received.format("%m/%d %H:%M:%S%.3f").to_string();
+        // As text "12/31 ..." would sort after "2025-...".
+        assert!(matches(r#"timestamp < "2025-01-01""#, &item));
+        assert!(matches(r#"timestamp > "2024-12-31 22:59:59.5""#, &item));
+        assert!(matches(r#"timestamp >= "2024-12-31T23:00:00""#, &item));
+        let epoch = item.received.timestamp();
+        assert!(matches(&format!("timestamp == {}", epoch), &item));
+        assert!(matches(&format!("timestamp < {}", epoch + 1), &item));
+        assert!(matches(r#"timestamp ~ "^12/31""#, &item));
+        for time in [
+            "09:30",
+            "09:30:00",
+            "09:30:00.25",
+            "2024-05-01",
+            "2024-05-01 09:30",
+        ] {
+            assert!(parse_time(time).is_some(), "{}", time);
+        }
+        assert!(Query::parse(r#"timestamp > "yesterday""#).is_err());
+        assert!(Query::parse("timestamp > true").is_err());
+    }
+
+    #[test]
+    fn syntax_errors_give_their_position() {
+        let error = |query: &str| Query::parse(query).unwrap_err();
+        assert_eq!(error(r#"exchange == "abc"#), "Unterminated string at 12");
+        assert_eq!(error("exchange # 1"), "Unexpected '#' at 9");
+        assert_eq!(
+            error(r#"exchange == "x" &&"#),
+            "Expected a field at end (18)"
+        );
+        assert_eq!(error(r#"(exchange == "x""#), "Expected ')' at end (16)");
+        assert_eq!(error(r#"exchange == "x")"#), "Unexpected ')' at 15");
+        assert_eq!(
+            error(r#"exchange == && x"#),
+            "Expected a value at 12, found '&&'"
+        );
+        assert_eq!(
+            error(r#"routing_key == "a" >= 2.50"#),
+            "Unexpected '>=' at 19"
+        );
+        assert_eq!(error("queue == 1"), "Unknown field 'queue' at 0");
+        assert_eq!(error("exchange == 1-2"), "Invalid number '1-2' at 12");
+    }
+}
@@ -0,0 +1,447 @@
+//! Regex filtering of the whole capture on a background thread, so that changing the filter
+//! does not freeze the UI. Each message's headers and body are summarised as they arrive by
+//! a signature of the trigrams they contain; literals which any match must contain are
+//! extracted from the regex, and only messages whose signatures contain all of their
+//! trigrams are actually searched. Matches are sent back in batches as they are found.
+
+use std::{
+    sync::{
+        mpsc::{self, TryRecvError},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use regex_syntax::hir::{Hir, HirKind};
+
+use super::model::{Highlight, HighlightField, ModelItem};
+
+/// Upper bound on the size of a signature, beyond which long texts simply saturate it.
+const MAX_SIGNATURE_BITS: usize = 1 << 15;
+/// How long to search before sending back the matches found so far.
+const BATCH_INTERVAL: Duration = Duration::from_millis(50);
+
+fn trigram_hash(trigram: &[u8]) -> usize {
+    let value = (trigram[0] as u32) << 16 | (trigram[1] as u32) << 8 | trigram[2] as u32;
+    // The high bits of a multiplicative hash are well mixed, unlike the low.
+    ((value as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15) >> 32) as usize
+}
+
+/// A bloom filter of the trigrams in a text, sized in proportion to it.
+struct Signature(Vec<u64>);
+
+impl Signature {
+    fn new(text: &str) -> Self {
+        let trigrams = text.len().saturating_sub(2);
+        let bits = (trigrams * 8)
+            .next_power_of_two()
+            .clamp(64, MAX_SIGNATURE_BITS);
+        let mut words = vec![0u64; bits / 64];
+        for trigram in text.as_bytes().windows(3) {
+            let bit = trigram_hash(trigram) & (bits - 1);
+            words[bit / 64] |= 1 << (bit % 64);
+        }
+        Self(words)
+    }
+
+    /// False if the text certainly lacks one of the trigrams.
+    fn may_contain(&self, hashes: &[usize]) -> bool {
+        let bits = self.0.len() * 64;
+        hashes.iter().all(|hash| {
+            let bit = hash & (bits - 1);
+            self.0[bit / 64] & (1 << (bit % 64)) != 0
+        })
+    }
+}
+
+/// Literals which must appear in any text matched by `hir`.
+fn required_literals(hir: &Hir, literals: &mut Vec<Vec<u8>>) {
+    match hir.kind() {
+        HirKind::Literal(literal) => literals.push(literal.0.to_vec()),
+        HirKind::Capture(capture) => required_literals(&capture.sub, literals),
+        HirKind::Repetition(repetition) if repetition.min > 0 => {
+            required_literals(&repetition.sub, literals)
+        }
+        HirKind::Concat(parts) => {
+            for part in parts {
+                required_literals(part, literals);
+            }
+        }
+        // Alternatives need not all be present, and other kinds are not literal.
+        _ => (),
+    }
+}
+
+/// Trigram hashes which must appear in any text matched by `pattern`, if any.
+fn required_trigrams(pattern: &str) -> Vec<usize> {
+    let Ok(hir) = regex_syntax::Parser::new().parse(pattern) else {
+        return Vec::default();
+    };
+    let mut literals = Vec::new();
+    required_literals(&hir, &mut literals);
+    let mut hashes: Vec<usize> = literals
+        .iter()
+        .flat_map(|literal| literal.windows(3).map(trigram_hash))
+        .collect();
+    hashes.sort_unstable();
+    hashes.dedup();
+    hashes
+}
+
+struct Document {
+    id: u64,
+    headers: Arc<String>,
+    body: Arc<String>,
+    headers_signature: Signature,
+    body_signature: Signature,
+}
+
+struct Search {
+    generation: u64,
+    regex: regex::Regex,
+    headers: bool,
+    body: bool,
+    trigrams: Vec<usize>,
+    /// Index of the next document to search.
+    position: usize,
+}
+
+impl Search {
+    fn highlights(&self, document: &Document) -> Vec<Highlight> {
+        let mut highlights = Vec::new();
+        let fields = [
+            (
+                self.headers,
+                HighlightField::Header,
+                &document.headers,
+                &document.headers_signature,
+            ),
+            (
+                self.body,
+                HighlightField::Body,
+                &document.body,
+                &document.body_signature,
+            ),
+        ];
+        for (enabled, field, text, signature) in fields {
+            if enabled && signature.may_contain(&self.trigrams) {
+                highlights.extend(self.regex.find_iter(text).map(|m| Highlight {
+                    field,
+                    start: m.start(),
+                    end: m.end(),
+                }));
+            }
+        }
+        highlights
+    }
+}
+
+enum IndexCommand {
+    Add {
+        id: u64,
+        headers: Arc<String>,
+        body: Arc<String>,
+    },
+    Clear,
+    Search {
+        generation: u64,
+        regex: regex::Regex,
+        headers: bool,
+        body: bool,
+    },
+    Cancel,
+}
+
+/// Matching messages (with their highlights) found by a search.
+struct SearchResults {
+    generation: u64,
+    matches: Vec<(u64, Vec<Highlight>)>,
+    /// Whether the search is complete.
+    done: bool,
+}
+
+fn index_task(rx: mpsc::Receiver<IndexCommand>, tx: mpsc::Sender<SearchResults>) {
+    let mut documents: Vec<Document> = Vec::new();
+    let mut search: Option<Search> = None;
+    loop {
+        // Commands take priority; only block for them when there is no searching to do.
+        let command = match &search {
+            Some(_) => match rx.try_recv() {
+                Ok(command) => Some(command),
+                Err(TryRecvError::Empty) => None,
+                Err(TryRecvError::Disconnected) => return,
+            },
+            None => match rx.recv() {
+                Ok(command) => Some(command),
+                Err(_) => return,
+            },
+        };
+        match command {
+            Some(IndexCommand::Add { id, headers, body }) => documents.push(Document {
+                id,
+                headers_signature: Signature::new(&headers),
+                body_signature: Signature::new(&body),
+                headers,
+                body,
+            }),
+            Some(IndexCommand::Clear) => documents.clear(),
+            Some(IndexCommand::Search {
+                generation,
+                regex,
+                headers,
+                body,
+            }) => {
+                search = Some(Search {
+                    generation,
+                    trigrams: required_trigrams(regex.as_str()),
+                    regex,
+                    headers,
+                    body,
+                    position: 0,
+                })
+            }
+            Some(IndexCommand::Cancel) => search = None,
+            None => {
+                let Some(current) = &mut search else {
+                    continue;
+                };
+                let deadline = Instant::now() + BATCH_INTERVAL;
+                let mut matches = Vec::new();
+                while current.position < documents.len() && Instant::now() < deadline {
+                    let document = &documents[current.position];
+                    let highlights = current.highlights(document);
+                    if !highlights.is_empty() {
+                        matches.push((document.id, highlights));
+                    }
+                    current.position += 1;
+                }
+                let done = current.position >= documents.len();
+                let results = SearchResults {
+                    generation: current.generation,
+                    matches,
+                    done,
+                };
+                if tx.send(results).is_err() {
+                    return;
+                }
+                if done {
+                    search = None;
+                }
+            }
+        }
+    }
+}
+
+pub struct SearchIndex {
+    tx: mpsc::Sender<IndexCommand>,
+    rx: mpsc::Receiver<SearchResults>,
+    /// Identifies the latest search, so that results of those superseded may be discarded.
+    generation: u64,
+    /// The latest search is yet to complete.
+    pub searching: bool,
+}
+
+impl Default for SearchIndex {
+    fn default() -> Self {
+        let (tx, rxc) = mpsc::channel();
+        let (txc, rx) = mpsc::channel();
+        let _index_thread = std::thread::spawn(move || index_task(rxc, txc));
+        Self {
+            tx,
+            rx,
+            generation: 0,
+            searching: false,
+        }
+    }
+}
+
+impl SearchIndex {
+    pub fn add(&self, item: &ModelItem) {
+        self.tx
+            .send(IndexCommand::Add {
+                id: item.id,
+                headers: item.headers.clone(),
+                body: item.body.clone(),
+            })
+            .expect("Search index thread exited");
+    }
+
+    pub fn clear(&self) {
+        self.tx
+            .send(IndexCommand::Clear)
+            .expect("Search index thread exited");
+    }
+
+    /// Start searching every message added so far, abandoning any earlier search.
+    pub fn search(&mut self, regex: &regex::Regex, headers: bool, body: bool) {
+        self.generation += 1;
+        self.searching = true;
+        self.tx
+            .send(IndexCommand::Search {
+                generation: self.generation,
+                regex: regex.clone(),
+                headers,
+                body,
+            })
+            .expect("Search index thread exited");
+    }
+
+    pub fn cancel(&mut self) {
+        if self.searching {
+            self.generation += 1;
+            self.searching = false;
+            self.tx
+                .send(IndexCommand::Cancel)
+                .expect("Search index thread exited");
+        }
+    }
+
+    /// Results of the latest search received since last asked.
+    pub fn results(&mut self) -> Vec<(u64, Vec<Highlight>)> {
+        let mut matches = Vec::new();
+        while let Ok(results) = self.rx.try_recv() {
+            if results.generation != self.generation {
+                continue;
+            }
+            matches.extend(results.matches);
+            if results.done {
+                self.searching = false;
+            }
+        }
+        matches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rabbit::DeliveryInfo;
+
+    const TEXTS: &[&str] = &[
+        "",
+        "a",
+        "ab",
+        "abc",
+        r#"{"tenant": "Acme", "order": 12345}"#,
+        r#"{"tenant": "ACME", "region": "eu-west-1"}"#,
+        "The quick brown fox jumps over the lazy dog",
+        "KELVIN \u{212A} and ß straße",
+        "order.created order.cancelled",
+        "λx. x + 1",
+    ];
+
+    const PATTERNS: &[&str] = &[
+        "a",
+        "ab",
+        "abc",
+        "acme",
+        "(?i)acme",
+        "(?i)kelvin k",
+        "(?i)STRASSE",
+        "(?i)straße",
+        "Acme|ACME",
+        "tenant|missing",
+        "(?:quick|slow) brown",
+        "order\\.(created|cancelled)",
+        "[Aa]cme",
+        "[0-9]{5}",
+        "\\d+",
+        "eu-west-\\d",
+        "fox.*dog",
+        "x?yz?",
+        "(fox)+ jumps",
+        "\\bdog\\b",
+        "λx",
+        "(?i)λX\\.",
+        ".",
+    ];
+
+    /// The pre-filter may pass documents which do not match, but must never reject one
+    /// which does.
+    #[test]
+    fn signatures_never_reject_a_match() {
+        for pattern in PATTERNS {
+            let regex = regex::Regex::new(pattern).unwrap();
+            let trigrams = required_trigrams(pattern);
+            for text in TEXTS {
+                if regex.is_match(text) {
+                    assert!(
+                        Signature::new(text).may_contain(&trigrams),
+                        "'{}' rejected '{}'",
+                        pattern,
+                        text
+                    );
+                }
+            }
+        }
+    }
+
+    /// Signatures saturate at their maximum size, so long texts must still be found.
+    #[test]
+    fn long_texts_are_not_rejected() {
+        let text: String = (0..100_000).map(|i| format!("{} ", i)).collect();
+        let signature = Signature::new(&text);
+        for pattern in ["99999 ", "12345 12346", "0 1 2 3"] {
+            assert!(signature.may_contain(&required_trigrams(pattern)));
+        }
+    }
+
+    fn literals(pattern: &str) -> Vec<String> {
+        let hir = regex_syntax::Parser::new().parse(pattern).unwrap();
+        let mut literals = Vec::new();
+        required_literals(&hir, &mut literals);
+        literals
+            .into_iter()
+            .map(|l| String::from_utf8(l).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn only_literals_common_to_every_match_are_required() {
+        assert_eq!(literals("acme"), vec!["acme"]);
+        assert_eq!(literals("foo\\d+bar"), vec!["foo", "bar"]);
+        assert_eq!(literals("(foo)+bar"), vec!["foo", "bar"]);
+        assert!(literals("(foo)*bar").iter().all(|l| l != "foo"));
+        assert!(literals("foo|bar").is_empty());
+        assert!(literals("[Ff]oo").iter().all(|l| !l.contains('F')));
+        assert!(literals("(?i)foo").is_empty());
+        // Too short to contribute a trigram.
+        assert!(required_trigrams("ab").is_empty());
+        assert!(!required_trigrams("abc").is_empty());
+    }
+
+    #[test]
+    fn signatures_reject_texts_lacking_required_trigrams() {
+        let signature = Signature::new("The quick brown fox jumps over the lazy dog");
+        assert!(!signature.may_contain(&required_trigrams("zebra crossing")));
+    }
+
+    #[test]
+    fn searches_find_every_match() {
+        let mut index = SearchIndex::default();
+        for (id, text) in TEXTS.iter().enumerate() {
+            let mut item =
+                ModelItem::new(text.to_string(), text.to_string(), DeliveryInfo::default());
+            item.id = id as u64;
+            index.add(&item);
+        }
+        for pattern in PATTERNS {
+            let regex = regex::Regex::new(pattern).unwrap();
+            index.search(&regex, true, false);
+            let deadline = Instant::now() + Duration::from_secs(10);
+            let mut found = Vec::new();
+            while index.searching && Instant::now() < deadline {
+                found.extend(index.results().into_iter().map(|(id, _)| id));
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            found.sort_unstable();
+            let expected: Vec<u64> = TEXTS
+                .iter()
+                .enumerate()
+                .filter(|(_, text)| regex.is_match(text))
+                .map(|(id, _)| id as u64)
+                .collect();
+            assert_eq!(found, expected, "{}", pattern);
+        }
+    }
+}
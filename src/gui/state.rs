@@ -1,3 +1,9 @@
+use std::collections::HashMap;
+
+use super::diff_window::Comparison;
+use super::query::Query;
+use super::top_talkers_window::TopTalkers;
+
 #[derive(PartialEq, Eq)]
 pub enum ConnectionStatus {
     Disconnected,
@@ -5,12 +11,179 @@ pub enum ConnectionStatus {
     Connected,
 }
 
+/// A filter string stored under a user-chosen name for later reuse.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct SavedFilter {
+    pub name: String,
+    pub filter_string: String,
+    pub structured: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum ProjectionSource {
+    Headers,
+    Body,
+}
+
+/// A user-defined column whose value is extracted from each message's headers or body
+/// by a JSONPath such as `$.order.id`.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct ProjectionColumn {
+    pub name: String,
+    pub source: ProjectionSource,
+    pub path: String,
+}
+
+impl Default for ProjectionColumn {
+    fn default() -> Self {
+        Self {
+            name: String::default(),
+            source: ProjectionSource::Body,
+            path: "$.".into(),
+        }
+    }
+}
+
+/// Text matching `pattern` is highlighted in `colour` wherever displayed, regardless of
+/// the filter.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct HighlightRule {
+    pub pattern: String,
+    pub colour: eframe::egui::Color32,
+    pub enabled: bool,
+    #[serde(skip)]
+    pub regex: Option<regex::Regex>,
+}
+
+impl Default for HighlightRule {
+    fn default() -> Self {
+        Self {
+            pattern: String::default(),
+            colour: eframe::egui::Color32::YELLOW,
+            enabled: true,
+            regex: None,
+        }
+    }
+}
+
+impl HighlightRule {
+    /// Must be called whenever the pattern changes; invalid patterns highlight nothing.
+    pub fn compile(&mut self) {
+        self.regex = if self.pattern.is_empty() {
+            None
+        } else {
+            regex::Regex::new(&self.pattern).ok()
+        };
+    }
+}
+
+/// A column of the message table.
+#[derive(Clone, Copy, PartialEq, Hash, serde::Serialize, serde::Deserialize)]
+pub enum DataColumn {
+    Timestamp,
+    Exchange,
+    RoutingKey,
+    ContentType,
+    Size,
+    MessageId,
+    Headers,
+    Body,
+    /// Index into `GuiState::projection_columns`
+    Projection(usize),
+}
+
+impl DataColumn {
+    pub fn label(&self, projection_columns: &[ProjectionColumn]) -> String {
+        match self {
+            DataColumn::Timestamp => "Timestamp".into(),
+            DataColumn::Exchange => "Exchange".into(),
+            DataColumn::RoutingKey => "Routing key".into(),
+            DataColumn::ContentType => "Content type".into(),
+            DataColumn::Size => "Size".into(),
+            DataColumn::MessageId => "Message ID".into(),
+            DataColumn::Headers => "Headers".into(),
+            DataColumn::Body => "Body".into(),
+            DataColumn::Projection(index) => projection_columns
+                .get(*index)
+                .map(|c| c.name.clone())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Position (by order within the layout), visibility and width of a table column.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct TableColumn {
+    pub column: DataColumn,
+    pub visible: bool,
+    pub width: f32,
+}
+
+impl TableColumn {
+    fn new(column: DataColumn, visible: bool, width: f32) -> Self {
+        Self {
+            column,
+            visible,
+            width,
+        }
+    }
+}
+
+pub fn default_table_columns() -> Vec<TableColumn> {
+    vec![
+        TableColumn::new(DataColumn::Timestamp, true, 150.0),
+        TableColumn::new(DataColumn::Exchange, false, 120.0),
+        TableColumn::new(DataColumn::RoutingKey, true, 160.0),
+        TableColumn::new(DataColumn::ContentType, false, 120.0),
+        TableColumn::new(DataColumn::Size, false, 60.0),
+        TableColumn::new(DataColumn::MessageId, false, 160.0),
+        TableColumn::new(DataColumn::Headers, true, 300.0),
+        TableColumn::new(DataColumn::Body, false, 300.0),
+    ]
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct SortOrder {
+    pub column: DataColumn,
+    pub ascending: bool,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ScrollTarget {
+    Top,
+    Bottom,
+}
+
 pub struct FilterState {
     pub filter_string: String,
     pub filter_headers: bool,
     pub filter_body: bool,
+    /// Interpret the filter string as a structured query rather than a regex.
+    pub structured: bool,
     pub regex: Option<regex::Regex>,
+    pub query: Option<Query>,
+    /// Display only bookmarked messages.
+    pub bookmarked_only: bool,
+    pub case_insensitive: bool,
+    /// Match the filter string as plain text rather than a regex.
+    pub literal: bool,
+    pub whole_word: bool,
+    /// Hide, rather than show, the messages which match.
+    pub invert: bool,
+    /// Recently applied filter strings, most recent first.
+    pub history: Vec<String>,
+    /// The match navigated to, as an item id and index into its highlights.
+    pub current_match: Option<(u64, usize)>,
+    /// The position of `current_match` among all the matches, as found for the given model
+    /// revision and display order (whether newest first).
+    pub match_position: Option<(u64, bool, Option<usize>)>,
+    /// One-shot request to scroll the current match into view.
+    pub scroll_to_match: bool,
+    /// Describes why the filter string failed to compile, whether as regex or query.
     pub regex_error: Option<String>,
+    pub saved_filters: Vec<SavedFilter>,
+    /// Name entered for the next saved filter.
+    pub save_as: String,
 }
 
 impl Default for FilterState {
@@ -19,8 +192,21 @@ impl Default for FilterState {
             filter_string: String::default(),
             filter_body: false,
             filter_headers: true,
+            structured: false,
             regex: None,
+            query: None,
+            bookmarked_only: false,
+            case_insensitive: false,
+            literal: false,
+            whole_word: false,
+            invert: false,
+            history: Vec::default(),
+            current_match: None,
+            match_position: None,
+            scroll_to_match: false,
             regex_error: None,
+            saved_filters: Vec::default(),
+            save_as: String::default(),
         }
     }
 }
@@ -32,6 +218,49 @@ pub struct GuiState {
     pub connection: ConnectionStatus,
     pub show_subscriptions: bool,
     pub add_subscription_parameters: Option<SubscriptionParams>,
+    pub projection_columns: Vec<ProjectionColumn>,
+    /// Layout of the message table, in display order.
+    pub table_columns: Vec<TableColumn>,
+    /// Table layouts of each connection profile, by `ConnectionParams::profile_key`.
+    pub table_layouts: HashMap<String, Vec<TableColumn>>,
+    pub show_columns: bool,
+    pub show_statistics: bool,
+    pub show_top_talkers: bool,
+    pub show_alerts: bool,
+    pub show_capture_log: bool,
+    pub show_highlights: bool,
+    pub highlight_rules: Vec<HighlightRule>,
+    pub show_subscription_sets: bool,
+    pub subscription_sets: Vec<SubscriptionSet>,
+    /// Name under which to save the current subscriptions.
+    pub subscription_set_name: String,
+    pub subscription_set_error: Option<String>,
+    /// Header by which to group top talkers; the routing key is used if empty.
+    pub top_talkers_header: String,
+    pub top_talkers: TopTalkers,
+    /// None implies arrival order.
+    pub sort: Option<SortOrder>,
+    /// Keep the newest message in view as data arrives.
+    pub follow_tail: bool,
+    /// Display the newest message at the top, rather than the bottom.
+    pub newest_first: bool,
+    /// One-shot request to scroll the data view.
+    pub scroll_to: Option<ScrollTarget>,
+    /// Cancel the consumer whilst paused, rather than buffering messages locally.
+    pub pause_cancels_consumer: bool,
+    /// Display RPC replies immediately beneath their requests
+    pub group_rpc: bool,
+    /// Requests without a reply after this long are flagged.
+    pub rpc_timeout_secs: f32,
+    /// Message chosen as the left hand side of the next comparison.
+    pub diff_selection: Option<u64>,
+    /// Messages being compared in the diff window, if open.
+    pub diff_items: Option<(u64, u64)>,
+    /// Compare JSON field by field, rather than line by line.
+    pub diff_structural: bool,
+    pub diff_show_unchanged: bool,
+    /// The comparison last shown in the diff window.
+    pub diff_comparison: Option<Comparison>,
 }
 
 impl Default for GuiState {
@@ -43,17 +272,118 @@ impl Default for GuiState {
             connection: ConnectionStatus::Disconnected,
             show_subscriptions: false,
             add_subscription_parameters: None,
+            projection_columns: Vec::default(),
+            table_columns: default_table_columns(),
+            table_layouts: HashMap::default(),
+            show_columns: false,
+            show_statistics: false,
+            show_top_talkers: false,
+            show_alerts: false,
+            show_capture_log: false,
+            show_highlights: false,
+            highlight_rules: Vec::default(),
+            show_subscription_sets: false,
+            subscription_sets: Vec::default(),
+            subscription_set_name: String::default(),
+            subscription_set_error: None,
+            top_talkers_header: String::default(),
+            top_talkers: TopTalkers::default(),
+            sort: None,
+            follow_tail: false,
+            newest_first: false,
+            scroll_to: None,
+            pause_cancels_consumer: false,
+            group_rpc: false,
+            rpc_timeout_secs: 30.0,
+            diff_selection: None,
+            diff_items: None,
+            diff_structural: true,
+            diff_show_unchanged: false,
+            diff_comparison: None,
+        }
+    }
+}
+
+/// The number of filter strings remembered in the history.
+const FILTER_HISTORY_LENGTH: usize = 20;
+
+impl FilterState {
+    /// Record the current filter string at the head of the history.
+    pub fn remember(&mut self) {
+        if self.filter_string.is_empty() || self.regex_error.is_some() {
+            return;
         }
+        self.history.retain(|f| *f != self.filter_string);
+        self.history.insert(0, self.filter_string.clone());
+        self.history.truncate(FILTER_HISTORY_LENGTH);
     }
 }
 
 impl GuiState {
+    /// Remember the table layout against the current connection profile.
+    pub fn store_table_layout(&mut self) {
+        self.table_layouts.insert(
+            self.connection_parameters.profile_key(),
+            self.table_columns.clone(),
+        );
+    }
+
+    /// Adopt the table layout remembered for the current connection profile, if any.
+    pub fn restore_table_layout(&mut self) {
+        self.table_columns = self
+            .table_layouts
+            .get(&self.connection_parameters.profile_key())
+            .cloned()
+            .unwrap_or_else(default_table_columns);
+        self.sync_table_columns();
+    }
+
+    /// Ensure the table layout has exactly one column per projection column.
+    pub fn sync_table_columns(&mut self) {
+        let count = self.projection_columns.len();
+        self.table_columns.retain(|c| match c.column {
+            DataColumn::Projection(index) => index < count,
+            _ => true,
+        });
+        for index in 0..count {
+            let column = DataColumn::Projection(index);
+            if !self.table_columns.iter().any(|c| c.column == column) {
+                self.table_columns
+                    .push(TableColumn::new(column, true, 120.0));
+            }
+        }
+    }
+
     pub fn update_regex(&mut self) {
         self.filter_state.regex_error = None;
+        self.filter_state.query = None;
         if self.filter_state.filter_string.is_empty() {
             self.filter_state.regex = None;
+        } else if self.filter_state.structured {
+            self.filter_state.regex = None;
+            match Query::parse(&self.filter_state.filter_string) {
+                Ok(query) => self.filter_state.query = Some(query),
+                Err(e) => self.filter_state.regex_error = Some(e),
+            }
         } else {
-            match regex::Regex::new(&self.filter_state.filter_string) {
+            let filter = &self.filter_state;
+            let mut pattern = if filter.literal {
+                regex::escape(&filter.filter_string)
+            } else {
+                filter.filter_string.clone()
+            };
+            if filter.whole_word {
+                pattern = whole_word(
+                    &pattern,
+                    filter.literal.then_some(filter.filter_string.as_str()),
+                );
+            }
+            // As a flag within the pattern, rather than an option of the builder, so that the
+            // search index (which inspects the pattern) is aware of it.
+            if filter.case_insensitive {
+                pattern = format!("(?i){}", pattern);
+            }
+            match regex::Regex::new(&pattern) {
                 Ok(regex) => {
                     self.filter_state.regex = Some(regex);
                 }
@@ -66,16 +396,46 @@ impl GuiState {
     }
 }
 
+/// Restrict `pattern` to matching whole words. A word boundary is only required at an end of
+/// a `literal` pattern which is itself part of a word, so that e.g. `#order` or `id:` still
+/// match; a regex is assumed to begin and end within words.
+fn whole_word(pattern: &str, literal: Option<&str>) -> String {
+    let is_word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+    let (start, end) = match literal {
+        Some(literal) => (
+            is_word(literal.chars().next()),
+            is_word(literal.chars().next_back()),
+        ),
+        None => (true, true),
+    };
+    format!(
+        "{}(?:{}){}",
+        if start { r"\b" } else { "" },
+        pattern,
+        if end { r"\b" } else { "" }
+    )
+}
+
+/// An exchange to which to make a wildcard subscription on connecting.
+#[derive(Clone, Default)]
+pub struct WildcardExchange {
+    pub name: String,
+    pub exchange_type: ExchangeType,
+}
+
 #[derive(Clone)]
 pub struct ConnectionParams {
     pub hostname: String,
     pub vhost: String,
-    pub exchange: String,
     pub username: String,
     pub password: String,
     pub tls: bool,
-    /// Create a default unqualified (i.e. to everything) subscription?
+    /// Create a default unqualified (i.e. to everything) subscription to each of
+    /// `wildcard_exchanges`?
     pub wildcard: bool,
+    pub wildcard_exchanges: Vec<WildcardExchange>,
+    /// Name of a subscription set to subscribe to on connecting, if any.
+    pub subscription_set: Option<String>,
     /// Usually 5672
     pub port: String,
     pub validation_error: Option<String>, // compute it here to avoid repeated recomputes in immediate mode.
@@ -91,7 +451,8 @@ impl Default for ConnectionParams {
             port: "5672".into(),
             validation_error: None,
             wildcard: true,
-            exchange: String::default(),
+            subscription_set: None,
+            wildcard_exchanges: vec![WildcardExchange::default()],
         }
     }
 }
@@ -99,7 +460,17 @@ impl Default for ConnectionParams {
 use lapin::uri;
 
 use super::add_subscription_window::SubscriptionParams;
+use super::subscription_sets::SubscriptionSet;
+use crate::rabbit::ExchangeType;
 impl ConnectionParams {
+    /// Identifies the broker and virtual host, under which per-connection settings are kept.
+    pub fn profile_key(&self) -> String {
+        format!(
+            "{}@{}:{}{}",
+            self.username, self.hostname, self.port, self.vhost
+        )
+    }
+
     pub fn build_url(&self) -> uri::AMQPUri {
         let scheme: uri::AMQPScheme = if self.tls {
             uri::AMQPScheme::AMQPS
@@ -138,6 +509,41 @@ impl ConnectionParams {
                 return;
             }
         }
+        // Nothing may be bound to the default exchange.
+        if self.wildcard && self.wildcard_exchanges.iter().any(|e| e.name.is_empty()) {
+            self.validation_error = Some("Wildcard exchange names cannot be empty".into());
+            return;
+        }
         self.validation_error = None;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn whole_word_matches(filter: &str, literal: bool, text: &str) -> bool {
+        let mut gui_state = GuiState::default();
+        gui_state.filter_state.filter_string = filter.into();
+        gui_state.filter_state.literal = literal;
+        gui_state.filter_state.whole_word = true;
+        gui_state.update_regex();
+        gui_state.filter_state.regex.unwrap().is_match(text)
+    }
+
+    #[test]
+    fn whole_words_may_begin_or_end_with_punctuation() {
+        assert!(whole_word_matches("#order", true, r##"{"tag": "#order"}"##));
+        assert!(!whole_word_matches(
+            "#order",
+            true,
+            r##"{"tag": "#orders"}"##
+        ));
+        assert!(whole_word_matches("id:", true, "id: 7"));
+        assert!(!whole_word_matches("id:", true, "uid: 7"));
+        assert!(whole_word_matches("a.b", true, "x a.b y"));
+        assert!(!whole_word_matches("a.b", true, "xa.b"));
+        assert!(whole_word_matches("ord(er)?", false, "an order"));
+        assert!(!whole_word_matches("ord(er)?", false, "orders"));
+    }
+}
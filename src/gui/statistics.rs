@@ -0,0 +1,308 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use eframe::egui::{
+    self, Align, Color32, Context, FontFamily, FontId, Layout, Pos2, RichText, Sense, Shape,
+    Stroke, Ui, Vec2, Window,
+};
+use egui_extras::{Column, TableBuilder};
+use uuid::Uuid;
+
+use crate::rabbit::DeliveryInfo;
+
+/// Rates are averaged over this many of the most recent whole seconds.
+const RATE_SECONDS: i64 = 10;
+pub const DEFAULT_HISTORY_MINUTES: u32 = 5;
+
+/// Per second message and byte counts over a sliding window, along with lifetime totals
+/// which are unaffected by the window.
+#[derive(Default)]
+pub struct RateSeries {
+    /// (unix second, messages, bytes), oldest first.
+    buckets: VecDeque<(i64, u64, u64)>,
+    pub total_messages: u64,
+    pub total_bytes: u64,
+}
+
+impl RateSeries {
+    fn record(&mut self, second: i64, bytes: u64) {
+        match self.buckets.back_mut() {
+            Some((s, messages, b)) if *s == second => {
+                *messages += 1;
+                *b += bytes;
+            }
+            _ => self.buckets.push_back((second, 1, bytes)),
+        }
+        self.total_messages += 1;
+        self.total_bytes += bytes;
+    }
+
+    fn prune(&mut self, oldest: i64) {
+        while self.buckets.front().is_some_and(|(s, _, _)| *s < oldest) {
+            self.buckets.pop_front();
+        }
+        // A series which has gone quiet need not hold on to its allocation.
+        if self.buckets.is_empty() {
+            self.buckets.shrink_to_fit();
+        }
+    }
+
+    /// Messages and bytes per second, averaged over the last `RATE_SECONDS` whole seconds.
+    pub fn rates(&self, now: i64) -> (f32, f32) {
+        let (messages, bytes) = self
+            .buckets
+            .iter()
+            .filter(|(s, _, _)| *s < now && *s >= now - RATE_SECONDS)
+            .fold((0, 0), |(m, b), (_, messages, bytes)| {
+                (m + messages, b + bytes)
+            });
+        (
+            messages as f32 / RATE_SECONDS as f32,
+            bytes as f32 / RATE_SECONDS as f32,
+        )
+    }
+
+    /// Messages in each of the `seconds` seconds up to (but excluding) `now`, oldest first.
+    pub fn history(&self, now: i64, seconds: i64) -> Vec<f32> {
+        let mut history = vec![0.0; seconds as usize];
+        for (s, messages, _) in &self.buckets {
+            let age = now - s;
+            if age >= 1 && age <= seconds {
+                history[(seconds - age) as usize] = *messages as f32;
+            }
+        }
+        history
+    }
+}
+
+pub struct Statistics {
+    pub overall: RateSeries,
+    pub by_routing_key: BTreeMap<String, RateSeries>,
+    /// Keyed by binding id
+    pub by_binding: HashMap<Uuid, RateSeries>,
+    pub history_minutes: u32,
+    /// The second at which counts were last pruned.
+    pruned: i64,
+}
+
+impl Default for Statistics {
+    fn default() -> Self {
+        Self {
+            overall: RateSeries::default(),
+            by_routing_key: BTreeMap::default(),
+            by_binding: HashMap::default(),
+            history_minutes: DEFAULT_HISTORY_MINUTES,
+            pruned: 0,
+        }
+    }
+}
+
+impl Statistics {
+    pub fn record(&mut self, info: &DeliveryInfo) {
+        let now = chrono::Utc::now().timestamp();
+        // Counts only accumulate as messages arrive, so pruning here (at most once a second)
+        // bounds them whether or not the window is open.
+        if now != self.pruned {
+            self.prune(now);
+        }
+        let bytes = info.size as u64;
+        self.overall.record(now, bytes);
+        self.by_routing_key
+            .entry(info.routing_key.clone())
+            .or_default()
+            .record(now, bytes);
+        for binding in &info.bindings {
+            self.by_binding
+                .entry(*binding)
+                .or_default()
+                .record(now, bytes);
+        }
+    }
+
+    /// Discard per second counts older than the history window, keeping the totals.
+    fn prune(&mut self, now: i64) {
+        self.pruned = now;
+        let oldest = now - self.history_minutes as i64 * 60;
+        for series in std::iter::once(&mut self.overall)
+            .chain(self.by_binding.values_mut())
+            .chain(self.by_routing_key.values_mut())
+        {
+            series.prune(oldest);
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.overall = RateSeries::default();
+        self.by_routing_key.clear();
+        self.by_binding.clear();
+    }
+}
+
+/// Human readable byte quantities, e.g. 1.5 KiB
+fn format_bytes(bytes: f32) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+/// A minimal line chart of `values`, scaled to fit the given size.
+fn sparkline(ui: &mut Ui, values: &[f32], size: Vec2) {
+    let (rect, _) = ui.allocate_exact_size(size, Sense::hover());
+    let max = values.iter().cloned().fold(1.0, f32::max);
+    let step = rect.width() / (values.len().max(2) - 1) as f32;
+    let points: Vec<Pos2> = values
+        .iter()
+        .enumerate()
+        .map(|(i, v)| {
+            Pos2::new(
+                rect.left() + i as f32 * step,
+                rect.bottom() - v / max * rect.height(),
+            )
+        })
+        .collect();
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 0.0, Color32::from_gray(24));
+    painter.add(Shape::line(points, Stroke::new(1.0, Color32::LIGHT_GREEN)));
+}
+
+impl super::App {
+    pub(crate) fn statistics_window(&mut self, ctx: &Context) {
+        if !self.gui_state.show_statistics {
+            return;
+        }
+        let now = chrono::Utc::now().timestamp();
+        let statistics = &mut self.statistics;
+        statistics.prune(now);
+        // Rates decay even if no more messages arrive, so keep redrawing.
+        ctx.request_repaint_after(std::time::Duration::from_secs(1));
+
+        Window::new("Statistics")
+            .movable(true)
+            .resizable(true)
+            .collapsible(false)
+            .open(&mut self.gui_state.show_statistics)
+            .show(ctx, |ui| {
+                let style = ui.style_mut();
+                style.override_font_id = Some(FontId {
+                    size: 16.0,
+                    family: FontFamily::Proportional,
+                });
+                ui.horizontal(|ui| {
+                    ui.label("History");
+                    ui.add(
+                        egui::DragValue::new(&mut statistics.history_minutes)
+                            .range(1..=60)
+                            .suffix(" min"),
+                    );
+                    if ui.button("Reset").clicked() {
+                        statistics.reset();
+                    }
+                });
+                ui.separator();
+
+                let history = statistics.history_minutes as i64 * 60;
+                let available_height = ui.available_height();
+                TableBuilder::new(ui)
+                    .striped(false)
+                    .resizable(false)
+                    .cell_layout(Layout::left_to_right(Align::Center))
+                    .column(Column::remainder())
+                    .columns(Column::auto().at_least(64.0), 4)
+                    .column(Column::auto())
+                    .auto_shrink(false)
+                    .max_scroll_height(available_height - 60.0) // leave room for window decorations etc
+                    .header(16.0, |mut header| {
+                        for heading in
+                            ["Source", "msg/s", "bytes/s", "Messages", "Bytes", "History"]
+                        {
+                            header.col(|ui| {
+                                ui.label(RichText::new(heading).strong());
+                            });
+                        }
+                    })
+                    .body(|mut body| {
+                        let bindings = self.queue_bindings.iter().filter_map(|b| {
+                            let label = format!("Binding {} / {}", b.exchange, b.routing_key);
+                            statistics.by_binding.get(&b.id).map(|s| (label, s))
+                        });
+                        let routing_keys = statistics
+                            .by_routing_key
+                            .iter()
+                            .map(|(k, v)| (k.clone(), v));
+                        let rows = std::iter::once(("All".to_string(), &statistics.overall))
+                            .chain(bindings)
+                            .chain(routing_keys);
+                        for (label, series) in rows {
+                            body.row(18.0, |mut row| {
+                                let (messages, bytes) = series.rates(now);
+                                row.col(|ui| {
+                                    ui.label(&label);
+                                });
+                                row.col(|ui| {
+                                    ui.label(format!("{:.1}", messages));
+                                });
+                                row.col(|ui| {
+                                    ui.label(format_bytes(bytes));
+                                });
+                                row.col(|ui| {
+                                    ui.label(series.total_messages.to_string());
+                                });
+                                row.col(|ui| {
+                                    ui.label(format_bytes(series.total_bytes as f32));
+                                });
+                                row.col(|ui| {
+                                    sparkline(
+                                        ui,
+                                        &series.history(now, history),
+                                        Vec2::new(200.0, 16.0),
+                                    );
+                                });
+                            });
+                        }
+                    });
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recording_prunes_old_counts_but_keeps_totals() {
+        let mut statistics = Statistics::default();
+        let info = |routing_key: &str| DeliveryInfo {
+            routing_key: routing_key.into(),
+            size: 10,
+            ..Default::default()
+        };
+        statistics.record(&info("old"));
+        // Age everything recorded so far beyond the history window.
+        let age = statistics.history_minutes as i64 * 60 + 1;
+        for series in statistics
+            .by_routing_key
+            .values_mut()
+            .chain(std::iter::once(&mut statistics.overall))
+        {
+            for bucket in series.buckets.iter_mut() {
+                bucket.0 -= age;
+            }
+        }
+        statistics.pruned -= 1;
+
+        statistics.record(&info("new"));
+        assert_eq!(
+            statistics.by_routing_key.keys().collect::<Vec<_>>(),
+            vec!["new", "old"]
+        );
+        let old = &statistics.by_routing_key["old"];
+        assert!(old.buckets.is_empty());
+        assert_eq!((old.total_messages, old.total_bytes), (1, 10));
+        assert_eq!(statistics.overall.buckets.len(), 1);
+        assert_eq!(statistics.overall.total_messages, 2);
+    }
+}
@@ -3,20 +3,25 @@ use eframe::egui::{self, Color32, Label, RichText, TopBottomPanel};
 use egui_phosphor::regular as icon;
 
 impl super::App {
-    pub(crate) fn status_bar(&self, ctx: &egui::Context) {
+    pub(crate) fn status_bar(&mut self, ctx: &egui::Context) {
         TopBottomPanel::bottom("status bar").show(ctx, |ui| {
             egui::menu::bar(ui, |ui| {
                 let funnel_colour: Color32;
                 let funnel_tooltip: String;
+                let filter_kind = if self.gui_state.filter_state.structured {
+                    "query"
+                } else {
+                    "regex"
+                };
                 if self.gui_state.filter_state.filter_string.is_empty() {
                     funnel_colour = Color32::GRAY;
-                    funnel_tooltip = "No filter regex".into();
+                    funnel_tooltip = format!("No filter {}", filter_kind);
                 } else if self.gui_state.filter_state.regex_error.is_some() {
                     funnel_colour = Color32::RED;
                     funnel_tooltip = self.gui_state.filter_state.regex_error.clone().unwrap();
                 } else {
                     funnel_colour = Color32::GREEN;
-                    funnel_tooltip = "Filtering with valid regex".into()
+                    funnel_tooltip = format!("Filtering with valid {}", filter_kind)
                 }
 
                 let connection_state_message = match self.gui_state.connection {
@@ -35,11 +40,34 @@ impl super::App {
                     }
                 };
 
-                let available_width = ui.available_width() - 24.0; // Leave space for menu icon and padding between elements
+                let unacknowledged = self.alerts.unacknowledged;
+                let searching = self.gui_data.searching();
+                // Leave space for menu icon (and alert badge and spinner, if shown) and padding
+                // between elements
+                let available_width = ui.available_width()
+                    - if unacknowledged > 0 { 72.0 } else { 24.0 }
+                    - if searching { 24.0 } else { 0.0 };
                 ui.add_sized(
                     [available_width, 24.0],
                     Label::new(connection_state_message),
                 );
+                if unacknowledged > 0
+                    && ui
+                        .add(
+                            Label::new(
+                                RichText::new(format!("{} {}", icon::BELL_RINGING, unacknowledged))
+                                    .color(Color32::RED),
+                            )
+                            .sense(egui::Sense::click()),
+                        )
+                        .on_hover_text("Alerts have fired")
+                        .clicked()
+                {
+                    self.gui_state.show_alerts = true;
+                }
+                if searching {
+                    ui.spinner().on_hover_text("Filtering");
+                }
                 ui.label(RichText::new(icon::FUNNEL).color(funnel_colour))
                     .on_hover_text(funnel_tooltip);
             })
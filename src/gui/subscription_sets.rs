@@ -0,0 +1,343 @@
+use eframe::egui::{
+    Align, Button, Color32, Context, FontFamily, FontId, Layout, RichText, TextEdit, Window,
+};
+use egui_extras::{Column, TableBuilder};
+use egui_phosphor::regular as icon;
+use uuid::Uuid;
+
+use crate::rabbit::field_table::{field_table_to_typed_json, typed_json_to_field_table};
+use crate::rabbit::{Binding, ExchangeType};
+
+use super::state::ConnectionStatus;
+
+/// A binding as saved, with its arguments as typed JSON so that they are restored exactly.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct SavedBinding {
+    pub exchange: String,
+    /// Absent from sets saved before it was recorded, when it is inferred from the binding.
+    #[serde(default)]
+    pub exchange_type: Option<ExchangeType>,
+    pub routing_key: String,
+    pub arguments: serde_json::Value,
+    /// Disabled bindings are restored as disabled, i.e. without being bound.
+    #[serde(default = "enabled_by_default")]
+    pub enabled: bool,
+}
+
+fn enabled_by_default() -> bool {
+    true
+}
+
+/// Whether two bindings are to the same exchange with the same routing key and arguments.
+fn same_binding(a: &Binding, b: &Binding) -> bool {
+    a.exchange == b.exchange && a.routing_key == b.routing_key && a.arguments == b.arguments
+}
+
+impl SavedBinding {
+    fn from_binding(binding: &Binding, enabled: bool) -> Self {
+        Self {
+            exchange: binding.exchange.clone(),
+            exchange_type: Some(binding.exchange_type),
+            routing_key: binding.routing_key.clone(),
+            arguments: field_table_to_typed_json(&binding.arguments),
+            enabled,
+        }
+    }
+
+    fn to_binding(&self) -> Result<Binding, String> {
+        let arguments = typed_json_to_field_table(&self.arguments)?;
+        let exchange_type = self.exchange_type.unwrap_or_else(|| {
+            if arguments.contains_key("x-match") {
+                ExchangeType::Headers
+            } else if self.routing_key.is_empty() && arguments.inner().is_empty() {
+                ExchangeType::Fanout
+            } else {
+                ExchangeType::Topic
+            }
+        });
+        Ok(Binding {
+            id: Uuid::new_v4(),
+            exchange: self.exchange.clone(),
+            exchange_type,
+            routing_key: self.routing_key.clone(),
+            arguments,
+        })
+    }
+}
+
+/// A named collection of bindings, which can be shared as a JSON file.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct SubscriptionSet {
+    pub name: String,
+    pub bindings: Vec<SavedBinding>,
+}
+
+impl SubscriptionSet {
+    fn validate(&self) -> Result<(), String> {
+        if self.name.is_empty() {
+            return Err("Subscription set has no name".into());
+        }
+        for binding in &self.bindings {
+            binding.to_binding()?;
+        }
+        Ok(())
+    }
+}
+
+fn read_set(path: &std::path::Path) -> anyhow::Result<SubscriptionSet> {
+    let set: SubscriptionSet = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+    set.validate().map_err(anyhow::Error::msg)?;
+    Ok(set)
+}
+
+fn write_set(path: &std::path::Path, set: &SubscriptionSet) -> anyhow::Result<()> {
+    std::fs::write(path, serde_json::to_string_pretty(set)?)?;
+    Ok(())
+}
+
+impl super::App {
+    /// Subscribe to `binding` unless the live connection already has (or is about to have)
+    /// the same binding. An identical disabled binding is enabled instead.
+    pub(crate) fn subscribe(&mut self, binding: Binding) {
+        if self
+            .queue_bindings
+            .iter()
+            .chain(self.binding_requests.iter())
+            .any(|b| same_binding(b, &binding))
+        {
+            return;
+        }
+        match self
+            .disabled_bindings
+            .iter()
+            .find(|b| same_binding(b, &binding))
+        {
+            Some(disabled) => {
+                if !self.toggling.contains(&disabled.id) {
+                    self.toggling.push(disabled.id);
+                    self.connection_manager.bind(disabled.clone());
+                }
+            }
+            None => {
+                self.binding_requests.push(binding.clone());
+                self.connection_manager.bind(binding);
+            }
+        }
+    }
+
+    /// Bind everything in the named set, in addition to any existing subscriptions. Those
+    /// already subscribed to are skipped, and those disabled are enabled if the set has
+    /// them enabled.
+    pub(crate) fn load_subscription_set(&mut self, name: &str) {
+        let Some(set) = self
+            .gui_state
+            .subscription_sets
+            .iter()
+            .find(|s| s.name == name)
+        else {
+            self.gui_state.subscription_set_error =
+                Some(format!("No subscription set named '{}'", name));
+            return;
+        };
+        for saved in set.bindings.clone() {
+            match saved.to_binding() {
+                Ok(binding) if saved.enabled => self.subscribe(binding),
+                Ok(binding) => {
+                    let known = self
+                        .queue_bindings
+                        .iter()
+                        .chain(self.binding_requests.iter())
+                        .chain(self.disabled_bindings.iter())
+                        .any(|b| same_binding(b, &binding));
+                    if !known {
+                        self.disabled_bindings.push(binding);
+                    }
+                }
+                Err(e) => {
+                    log::error!("Invalid binding in subscription set '{}': {}", name, e);
+                    self.gui_state.subscription_set_error = Some(e);
+                }
+            }
+        }
+    }
+
+    /// Save the current subscriptions, enabled or not, under the given name, replacing any
+    /// set of that name.
+    fn save_subscription_set(&mut self, name: String) {
+        let enabled = self.queue_bindings.iter().map(|b| (b, true));
+        let disabled = self.disabled_bindings.iter().map(|b| (b, false));
+        let set = SubscriptionSet {
+            name,
+            bindings: enabled
+                .chain(disabled)
+                .map(|(binding, enabled)| SavedBinding::from_binding(binding, enabled))
+                .collect(),
+        };
+        let sets = &mut self.gui_state.subscription_sets;
+        match sets.iter_mut().find(|s| s.name == set.name) {
+            Some(existing) => *existing = set,
+            None => sets.push(set),
+        }
+    }
+
+    fn import_subscription_set(&mut self) {
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("JSON", &["json"])
+            .pick_file()
+        else {
+            return;
+        };
+        match read_set(&path) {
+            Ok(set) => {
+                let sets = &mut self.gui_state.subscription_sets;
+                sets.retain(|s| s.name != set.name);
+                sets.push(set);
+            }
+            Err(e) => {
+                log::error!("Failed to import {}: {}", path.display(), e);
+                self.gui_state.subscription_set_error = Some(e.to_string());
+            }
+        }
+    }
+
+    fn export_subscription_set(&mut self, index: usize) {
+        let set = &self.gui_state.subscription_sets[index];
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("JSON", &["json"])
+            .set_file_name(format!("{}.json", set.name))
+            .save_file()
+        else {
+            return;
+        };
+        if let Err(e) = write_set(&path, set) {
+            log::error!("Failed to export to {}: {}", path.display(), e);
+            self.gui_state.subscription_set_error = Some(e.to_string());
+        }
+    }
+
+    /// Window for saving the current subscriptions as a named set, and for loading, sharing
+    /// and deleting sets.
+    pub(crate) fn subscription_sets_window(&mut self, ctx: &Context) {
+        let connected = self.gui_state.connection == ConnectionStatus::Connected;
+        let have_bindings = !self.queue_bindings.is_empty() || !self.disabled_bindings.is_empty();
+        let mut save = false;
+        let mut import = false;
+        let mut load_index = None;
+        let mut export_index = None;
+        let mut delete_index = None;
+
+        Window::new("Subscription sets")
+            .movable(true)
+            .resizable(true)
+            .collapsible(false)
+            .open(&mut self.gui_state.show_subscription_sets)
+            .show(ctx, |ui| {
+                let style = ui.style_mut();
+                style.override_font_id = Some(FontId {
+                    size: 16.0,
+                    family: FontFamily::Proportional,
+                });
+                ui.horizontal(|ui| {
+                    ui.add(
+                        TextEdit::singleline(&mut self.gui_state.subscription_set_name)
+                            .hint_text("Name"),
+                    );
+                    save = ui
+                        .add_enabled(
+                            have_bindings && !self.gui_state.subscription_set_name.is_empty(),
+                            Button::new("Save current"),
+                        )
+                        .on_hover_text(
+                            "Save the current subscriptions, including disabled ones, under this name",
+                        )
+                        .clicked();
+                    import = ui.button("Import...").clicked();
+                });
+                ui.separator();
+
+                TableBuilder::new(ui)
+                    .id_salt("subscription_sets")
+                    .striped(false)
+                    .resizable(false)
+                    .cell_layout(Layout::left_to_right(Align::Center))
+                    .column(Column::remainder().at_least(120.0))
+                    .column(Column::auto())
+                    .column(Column::auto().at_least(96.0))
+                    .auto_shrink([false, true])
+                    .header(16.0, |mut header| {
+                        for heading in ["Name", "Subscriptions", ""] {
+                            header.col(|ui| {
+                                ui.label(heading);
+                            });
+                        }
+                    })
+                    .body(|mut body| {
+                        for (index, set) in self.gui_state.subscription_sets.iter().enumerate() {
+                            body.row(20.0, |mut row| {
+                                row.col(|ui| {
+                                    ui.label(&set.name);
+                                });
+                                row.col(|ui| {
+                                    ui.label(set.bindings.len().to_string());
+                                });
+                                row.col(|ui| {
+                                    if ui
+                                        .add_enabled(
+                                            connected,
+                                            Button::new(icon::PLAY).fill(Color32::TRANSPARENT),
+                                        )
+                                        .on_hover_text("Subscribe to all of these")
+                                        .on_disabled_hover_text("Not connected")
+                                        .clicked()
+                                    {
+                                        load_index = Some(index);
+                                    }
+                                    if ui
+                                        .add(Button::new(icon::EXPORT).fill(Color32::TRANSPARENT))
+                                        .on_hover_text("Export")
+                                        .clicked()
+                                    {
+                                        export_index = Some(index);
+                                    }
+                                    if ui
+                                        .add(
+                                            Button::new(
+                                                RichText::new(icon::TRASH).color(Color32::RED),
+                                            )
+                                            .fill(Color32::TRANSPARENT),
+                                        )
+                                        .clicked()
+                                    {
+                                        delete_index = Some(index);
+                                    }
+                                });
+                            });
+                        }
+                    });
+
+                if let Some(error) = &self.gui_state.subscription_set_error {
+                    ui.label(RichText::new(error).color(Color32::RED));
+                }
+            });
+
+        if save || import || load_index.is_some() || export_index.is_some() {
+            self.gui_state.subscription_set_error = None;
+        }
+        if save {
+            self.save_subscription_set(self.gui_state.subscription_set_name.clone());
+        }
+        if import {
+            self.import_subscription_set();
+        }
+        if let Some(index) = load_index {
+            let name = self.gui_state.subscription_sets[index].name.clone();
+            self.load_subscription_set(&name);
+        }
+        if let Some(index) = export_index {
+            self.export_subscription_set(index);
+        }
+        if let Some(index) = delete_index {
+            self.gui_state.subscription_sets.remove(index);
+        }
+    }
+}
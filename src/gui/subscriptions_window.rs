@@ -1,11 +1,81 @@
 use eframe::egui::{
-    Align, Button, Color32, Context, FontFamily, FontId, Label, Layout, RichText, Window,
+    self, Align, Button, Color32, Context, FontFamily, FontId, Label, Layout, RichText, Window,
 };
 use egui_extras::{Column, TableBuilder};
 use egui_phosphor::regular as icon;
 
+use crate::rabbit::Binding;
+
+use super::add_subscription_window::SubscriptionParams;
+use super::state::ConnectionStatus;
+
 impl super::App {
+    /// Take on an edit the broker has made, closing the dialogue it was made in.
+    pub(crate) fn rebound(&mut self, binding: Binding) {
+        if self.editing(&binding) {
+            self.gui_state.add_subscription_parameters = None;
+        }
+        if let Some(b) = self.queue_bindings.iter_mut().find(|b| b.id == binding.id) {
+            *b = binding;
+        }
+    }
+
+    /// Keep an edit which could not be applied, the subscription having been disabled or
+    /// deleted since editing began: a disabled subscription takes on the edit (to apply when
+    /// it is enabled again), and otherwise the edit is offered back as a new subscription.
+    pub(crate) fn rebind_failed(&mut self, binding: Binding) {
+        let editing = self.editing(&binding);
+        if let Some(disabled) = self
+            .disabled_bindings
+            .iter_mut()
+            .find(|b| b.id == binding.id)
+        {
+            *disabled = binding;
+            if editing {
+                self.gui_state.add_subscription_parameters = None;
+            }
+            return;
+        }
+        let notice = "The subscription being edited has been deleted; add this in its place?";
+        if let Some(params) = self
+            .gui_state
+            .add_subscription_parameters
+            .as_mut()
+            .filter(|_| editing)
+        {
+            params.replacing = None;
+            params.rebinding = false;
+            params.notice = Some(notice.into());
+            return;
+        }
+        match SubscriptionParams::from_binding(&binding) {
+            Ok(mut params) if self.gui_state.add_subscription_parameters.is_none() => {
+                params.replacing = None;
+                params.notice = Some(notice.into());
+                self.gui_state.add_subscription_parameters = Some(params);
+            }
+            _ => log::warn!(
+                "Discarding an edit of a deleted subscription to '{}'",
+                binding.exchange
+            ),
+        }
+    }
+
+    /// Whether the dialogue is waiting on the broker to make this edit.
+    fn editing(&self, binding: &Binding) -> bool {
+        self.gui_state
+            .add_subscription_parameters
+            .as_ref()
+            .is_some_and(|p| p.rebinding && p.replacing == Some(binding.id))
+    }
+
     pub(crate) fn subscriptions_window(&mut self, ctx: &Context) {
+        let mut edit = None;
+        // The binding to enable or disable, and whether to enable it.
+        let mut toggle = None;
+        let mut delete_disabled = None;
+        let connected = self.gui_state.connection == ConnectionStatus::Connected;
+
         Window::new("Subscriptions")
             .movable(true)
             .resizable(true)
@@ -24,13 +94,18 @@ impl super::App {
                     .cell_layout(Layout::right_to_left(Align::Center))
                     .column(Column::auto())
                     .column(Column::auto())
+                    .column(Column::auto())
                     .column(Column::remainder())
-                    .column(Column::auto().at_least(32.0))
+                    .column(Column::auto())
+                    .column(Column::auto().at_least(64.0))
                     .auto_shrink(false)
                     .max_scroll_height(available_height - 120.0); // leave room for window decorations etc
 
                 table
                     .header(16.0, |mut header| {
+                        header.col(|ui| {
+                            ui.label("");
+                        });
                         header.col(|ui| {
                             ui.label("Exchange");
                         });
@@ -40,30 +115,72 @@ impl super::App {
                         header.col(|ui| {
                             ui.label("Arguments");
                         });
+                        header.col(|ui| {
+                            ui.label("Messages");
+                        });
                         header.col(|ui| {
                             ui.label("");
                         });
                     })
                     .body(|mut body| {
-                        for subscription in &self.queue_bindings {
+                        let subscriptions = self
+                            .queue_bindings
+                            .iter()
+                            .map(|b| (b, true))
+                            .chain(self.disabled_bindings.iter().map(|b| (b, false)));
+                        for (subscription, enabled) in subscriptions {
+                            // Disabled subscriptions are greyed out.
+                            let styled = |text: String| match enabled {
+                                true => RichText::new(text),
+                                false => RichText::new(text).weak(),
+                            };
                             body.row(16.0, |mut row| {
                                 row.col(|ui| {
-                                    ui.label(&subscription.exchange);
+                                    let mut checked = enabled;
+                                    if ui
+                                        .add_enabled(
+                                            connected && !self.toggling.contains(&subscription.id),
+                                            egui::Checkbox::without_text(&mut checked),
+                                        )
+                                        .on_disabled_hover_text(
+                                            "Subscriptions can only be enabled or disabled whilst connected",
+                                        )
+                                        .on_hover_text(if enabled {
+                                            "Unbind for now, keeping the subscription"
+                                        } else {
+                                            "Bind again"
+                                        })
+                                        .changed()
+                                    {
+                                        toggle = Some(((*subscription).clone(), checked));
+                                    }
+                                });
+                                row.col(|ui| {
+                                    ui.label(styled(subscription.exchange.clone()));
                                 });
                                 row.col(|ui| {
-                                    ui.label(&subscription.routing_key);
+                                    ui.label(styled(subscription.routing_key.clone()));
                                 });
                                 row.col(|ui| {
                                     ui.add(
-                                        Label::new(
+                                        Label::new(styled(
                                             crate::rabbit::field_table_to_json(
                                                 &subscription.arguments,
                                             )
                                             .to_string(),
-                                        )
+                                        ))
                                         .wrap(),
                                     );
                                 });
+                                row.col(|ui| {
+                                    let count = self
+                                        .statistics
+                                        .by_binding
+                                        .get(&subscription.id)
+                                        .map(|s| s.total_messages)
+                                        .unwrap_or_default();
+                                    ui.label(count.to_string());
+                                });
                                 row.col(|ui| {
                                     if ui
                                         .add(
@@ -74,12 +191,57 @@ impl super::App {
                                         )
                                         .clicked()
                                     {
-                                        self.connection_manager.unbind((*subscription).clone());
+                                        if enabled {
+                                            self.connection_manager.unbind((*subscription).clone());
+                                        } else {
+                                            delete_disabled = Some(subscription.id);
+                                        }
+                                    }
+                                    let params = SubscriptionParams::from_binding(subscription)
+                                        .and_then(|params| match (connected, enabled) {
+                                            (true, true) => Ok(params),
+                                            (false, _) => {
+                                                Err("Connect to edit the subscription".into())
+                                            }
+                                            (true, false) => {
+                                                Err("Enable the subscription to edit it".into())
+                                            }
+                                        });
+                                    let response = ui.add_enabled(
+                                        params.is_ok(),
+                                        Button::new(icon::PENCIL).fill(Color32::TRANSPARENT),
+                                    );
+                                    match params {
+                                        Ok(params) => {
+                                            if response.on_hover_text("Edit").clicked() {
+                                                edit = Some(params);
+                                            }
+                                        }
+                                        Err(e) => {
+                                            response.on_disabled_hover_text(e);
+                                        }
                                     }
                                 });
                             })
                         }
                     });
             });
+
+        // Subscriptions move between the enabled and disabled lists only once the broker
+        // has confirmed the change; see `process_connection_update`.
+        if let Some((binding, enable)) = toggle {
+            self.toggling.push(binding.id);
+            if enable {
+                self.connection_manager.bind(binding);
+            } else {
+                self.connection_manager.unbind(binding);
+            }
+        }
+        if let Some(id) = delete_disabled {
+            self.disabled_bindings.retain(|b| b.id != id);
+        }
+        if edit.is_some() {
+            self.gui_state.add_subscription_parameters = edit;
+        }
     }
 }
@@ -0,0 +1,314 @@
+use std::collections::HashMap;
+
+use eframe::egui::{
+    Align, Button, Color32, Context, FontFamily, FontId, Layout, RichText, TextEdit, Window,
+};
+use egui_extras::{Column, TableBuilder};
+use serde_json::Value;
+
+use super::model::{Model, ModelItem};
+
+/// Aggregate of the captured messages sharing a routing key (or header value).
+struct Talker {
+    /// The routing key, or the header value as JSON, so that e.g. the string "42" and the
+    /// number 42 are told apart.
+    key: String,
+    /// The header value, if grouping by header.
+    value: Value,
+    count: usize,
+    bytes: usize,
+    first_seen: chrono::DateTime<chrono::Local>,
+    last_seen: chrono::DateTime<chrono::Local>,
+}
+
+/// Quote a string as a literal in the structured query language.
+fn query_literal(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// The literal with which to compare a header in the structured query language, if the
+/// value can be expressed as one.
+fn header_literal(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(query_literal(s)),
+        Value::Bool(_) | Value::Number(_) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+/// Whether a header can be named in a structured query, where `.` and `[` separate the
+/// segments of a path and only some characters are permitted.
+fn header_expressible(header: &str) -> bool {
+    !header.is_empty()
+        && header
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '_' | '-'))
+}
+
+/// The captured messages grouped by routing key or header, updated as messages arrive
+/// rather than recomputed every frame.
+#[derive(Default)]
+pub struct TopTalkers {
+    header: String,
+    talkers: HashMap<String, Talker>,
+    /// `Model::arrivals` and the number of items when last updated.
+    arrivals: u64,
+    len: usize,
+}
+
+impl TopTalkers {
+    fn add(&mut self, item: &ModelItem) {
+        let (key, value) = if self.header.is_empty() {
+            (item.info.routing_key.clone(), Value::Null)
+        } else {
+            let mut headers: Value = serde_json::from_str(&item.headers).unwrap_or_default();
+            let value = headers
+                .get_mut(&self.header)
+                .map(Value::take)
+                .unwrap_or_default();
+            match &value {
+                Value::Null => return,
+                v => (v.to_string(), value),
+            }
+        };
+        let talker = self.talkers.entry(key.clone()).or_insert(Talker {
+            key,
+            value,
+            count: 0,
+            bytes: 0,
+            first_seen: item.received,
+            last_seen: item.received,
+        });
+        talker.count += 1;
+        talker.bytes += item.info.size;
+        talker.first_seen = talker.first_seen.min(item.received);
+        talker.last_seen = talker.last_seen.max(item.received);
+    }
+
+    /// Take account of the items that have arrived since last updated, starting afresh if
+    /// the grouping has changed or items have otherwise been added or removed.
+    fn update(&mut self, model: &Model, header: &str) {
+        let mut added = 0;
+        if header == self.header && model.data.len() >= self.len {
+            for id in self.arrivals..model.arrivals() {
+                if let Some(item) = model.get_listed(id) {
+                    self.add(item);
+                    added += 1;
+                }
+            }
+        }
+        if header != self.header || self.len + added != model.data.len() {
+            self.header = header.to_string();
+            self.talkers.clear();
+            for item in &model.data {
+                self.add(item);
+            }
+        }
+        self.arrivals = model.arrivals();
+        self.len = model.data.len();
+    }
+
+    /// The groups, largest first.
+    fn sorted(&self) -> Vec<&Talker> {
+        let mut talkers: Vec<&Talker> = self.talkers.values().collect();
+        talkers.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.key.cmp(&b.key)));
+        talkers
+    }
+}
+
+impl super::App {
+    /// Restrict the main view to the messages belonging to the given talker.
+    fn filter_to_talker(&mut self, filter_string: String) {
+        let filter_state = &mut self.gui_state.filter_state;
+        filter_state.structured = true;
+        filter_state.invert = false;
+        filter_state.filter_string = filter_string;
+        self.gui_state.update_regex();
+        self.gui_data.filter_all(&self.gui_state);
+    }
+
+    pub(crate) fn top_talkers_window(&mut self, ctx: &Context) {
+        if !self.gui_state.show_top_talkers {
+            return;
+        }
+        self.gui_state
+            .top_talkers
+            .update(&self.gui_data, &self.gui_state.top_talkers_header);
+        let mut selected = None;
+        let mut open = self.gui_state.show_top_talkers;
+
+        Window::new("Top talkers")
+            .movable(true)
+            .resizable(true)
+            .collapsible(false)
+            .open(&mut open)
+            .show(ctx, |ui| {
+                let style = ui.style_mut();
+                style.override_font_id = Some(FontId {
+                    size: 16.0,
+                    family: FontFamily::Proportional,
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Group by header");
+                    ui.add(
+                        TextEdit::singleline(&mut self.gui_state.top_talkers_header)
+                            .hint_text("(routing key)"),
+                    );
+                });
+                ui.separator();
+
+                let header = self.gui_state.top_talkers_header.as_str();
+                let key_heading = if header.is_empty() {
+                    "Routing key"
+                } else {
+                    header
+                };
+                // The filter selecting a talker's messages, or why there cannot be one.
+                let talker_filter = |talker: &Talker| -> Result<String, &str> {
+                    if header.is_empty() {
+                        return Ok(format!("routing_key == {}", query_literal(&talker.key)));
+                    }
+                    if !header_expressible(header) {
+                        return Err("This header cannot be named in a structured query");
+                    }
+                    match header_literal(&talker.value) {
+                        Some(literal) => Ok(format!("headers.{} == {}", header, literal)),
+                        None => Err("This value cannot be expressed in a structured query"),
+                    }
+                };
+                let available_height = ui.available_height();
+                TableBuilder::new(ui)
+                    .striped(true)
+                    .resizable(false)
+                    .cell_layout(Layout::left_to_right(Align::Center))
+                    .column(Column::remainder())
+                    .columns(Column::auto().at_least(64.0), 4)
+                    .auto_shrink(false)
+                    .max_scroll_height(available_height - 60.0) // leave room for window decorations etc
+                    .header(16.0, |mut header| {
+                        for heading in [key_heading, "Messages", "Bytes", "First seen", "Last seen"]
+                        {
+                            header.col(|ui| {
+                                ui.label(RichText::new(heading).strong());
+                            });
+                        }
+                    })
+                    .body(|mut body| {
+                        for talker in self.gui_state.top_talkers.sorted() {
+                            body.row(18.0, |mut row| {
+                                row.col(|ui| {
+                                    let filter = talker_filter(talker);
+                                    let response = ui.add_enabled(
+                                        filter.is_ok(),
+                                        Button::new(&talker.key).fill(Color32::TRANSPARENT),
+                                    );
+                                    match filter {
+                                        Ok(filter) => {
+                                            if response
+                                                .on_hover_text("Show only these messages")
+                                                .clicked()
+                                            {
+                                                selected = Some(filter);
+                                            }
+                                        }
+                                        Err(e) => {
+                                            response.on_disabled_hover_text(e);
+                                        }
+                                    }
+                                });
+                                row.col(|ui| {
+                                    ui.label(talker.count.to_string());
+                                });
+                                row.col(|ui| {
+                                    ui.label(talker.bytes.to_string());
+                                });
+                                row.col(|ui| {
+                                    ui.label(talker.first_seen.format("%H:%M:%S").to_string());
+                                });
+                                row.col(|ui| {
+                                    ui.label(talker.last_seen.format("%H:%M:%S").to_string());
+                                });
+                            });
+                        }
+                    });
+            });
+
+        self.gui_state.show_top_talkers = open;
+        if let Some(filter_string) = selected {
+            self.filter_to_talker(filter_string);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gui::query::Query;
+    use crate::rabbit::DeliveryInfo;
+
+    fn item(headers: &str) -> ModelItem {
+        ModelItem::new(headers.into(), String::default(), DeliveryInfo::default())
+    }
+
+    #[test]
+    fn typed_header_values_select_their_messages() {
+        for (value, other) in [
+            (r#""acme""#, r#""other""#),
+            ("true", "false"),
+            ("42", "43"),
+            ("-1.5", "1.5"),
+            (r#""say \"hi\"""#, r#""hi""#),
+        ] {
+            let literal = header_literal(&serde_json::from_str(value).unwrap()).unwrap();
+            let query = Query::parse(&format!("headers.tenant-id == {}", literal)).unwrap();
+            assert!(query.matches(&item(&format!(r#"{{"tenant-id": {}}}"#, value))));
+            assert!(!query.matches(&item(&format!(r#"{{"tenant-id": {}}}"#, other))));
+        }
+    }
+
+    #[test]
+    fn inexpressible_headers_and_values_are_refused() {
+        assert!(header_expressible("x-tenant_id"));
+        assert!(!header_expressible("tenant.id"));
+        assert!(!header_expressible("tenant id"));
+        assert!(!header_expressible("tenant[0]"));
+        assert!(header_literal(&serde_json::json!({"a": 1})).is_none());
+        assert!(header_literal(&serde_json::json!([1])).is_none());
+    }
+
+    #[test]
+    fn talkers_are_updated_as_messages_arrive() {
+        let mut model = Model::default();
+        let mut talkers = TopTalkers::default();
+        model.push(item(r#"{"tenant": "a"}"#), None);
+        talkers.update(&model, "tenant");
+        model.push(item(r#"{"tenant": "a"}"#), None);
+        model.push(item(r#"{"tenant": true}"#), None);
+        model.push(item(r#"{"tenant": "true"}"#), None);
+        model.push(item(r#"{"tenant": 42}"#), None);
+        model.push(item(r#"{"tenant": "42"}"#), None);
+        model.push(item("{}"), None);
+        talkers.update(&model, "tenant");
+        let counts: Vec<(&str, usize)> = talkers
+            .sorted()
+            .iter()
+            .map(|t| (t.key.as_str(), t.count))
+            .collect();
+        assert_eq!(
+            counts,
+            vec![
+                (r#""a""#, 2),
+                (r#""42""#, 1),
+                (r#""true""#, 1),
+                ("42", 1),
+                ("true", 1)
+            ]
+        );
+
+        model.clear();
+        model.push(item(r#"{"tenant": "b"}"#), None);
+        talkers.update(&model, "tenant");
+        assert_eq!(talkers.sorted().len(), 1);
+        assert_eq!(talkers.sorted()[0].key, r#""b""#);
+    }
+}
@@ -1,110 +1,630 @@
-use super::model::{Highlight, HighlightField};
+use std::collections::BinaryHeap;
+
+use crate::rabbit::Binding;
+
+use super::json_tree_view::{json_path_at, json_tree};
+use super::model::{Highlight, HighlightField, ModelItem, Rpc, RuleHighlight};
+use super::state::{DataColumn, GuiState, ScrollTarget, SortOrder};
 use eframe::egui::{
-    self, text::LayoutJob, CentralPanel, Color32, Grid, RichText, ScrollArea, TextFormat, Ui,
+    self, text::LayoutJob, Align, CentralPanel, Color32, Key, Layout, RichText, TextFormat, Ui,
 };
+use egui_extras::{Column, TableBuilder, TableRow};
 use egui_phosphor::regular as icon;
 
+const ROW_HEIGHT: f32 = 20.0;
+
+/// A row of the table: a message's summary or, beneath it, its expanded trees.
+struct DisplayRow {
+    id: u64,
+    height: f32,
+    /// Whether this is a reply shown beneath its request.
+    nested: bool,
+    trees: bool,
+}
+
+/// Something requested via a row's context menu, acted upon once the table is drawn.
+enum RowAction {
+    SelectForDiff(u64),
+    Diff(u64, u64),
+    ToggleBookmark(u64),
+}
+
 impl super::App {
+    /// A clickable column heading which cycles between ascending, descending and
+    /// arrival order sorting on the given column.
+    fn sort_heading(&mut self, ui: &mut Ui, label: &str, column: DataColumn) {
+        let sort = self.gui_state.sort.filter(|s| s.column == column);
+        let text = match sort {
+            None => label.to_string(),
+            Some(s) if s.ascending => format!("{} {}", label, icon::CARET_UP),
+            Some(_) => format!("{} {}", label, icon::CARET_DOWN),
+        };
+        if ui
+            .add(egui::Button::new(RichText::new(text).strong()).fill(Color32::TRANSPARENT))
+            .clicked()
+        {
+            self.gui_state.sort = match sort {
+                None => Some(SortOrder {
+                    column,
+                    ascending: true,
+                }),
+                Some(s) if s.ascending => Some(SortOrder {
+                    column,
+                    ascending: false,
+                }),
+                Some(_) => None,
+            };
+            self.gui_data.sort(self.gui_state.sort);
+        }
+    }
+
     pub(crate) fn tree_data_view(&mut self, ctx: &egui::Context) {
         CentralPanel::default().show(ctx, |ui| {
-            // TODO utilize show_viewport instead to render only visible section
-            ScrollArea::vertical().auto_shrink(false).show(ui, |ui| {
-                Grid::new("data")
-                    .num_columns(3)
-                    .min_col_width(0.0)
-                    .show(ui, |ui| {
-                        for item in &mut self.gui_data.data {
-                            // Omit rows that should be filtered according to the current regex.
-                            if self.gui_state.filter_state.regex.is_some()
-                                && item.highlights.is_empty()
-                            {
-                                continue;
-                            }
-                            let caret = if item.expanded {
-                                icon::CARET_DOWN
-                            } else {
-                                icon::CARET_RIGHT
-                            };
-                            if ui
-                                .add(
-                                    egui::Button::new(RichText::new(caret))
-                                        .fill(Color32::TRANSPARENT),
-                                )
-                                .clicked()
-                            {
-                                item.expanded = !item.expanded;
-                            }
-                            ui.label(RichText::new(&item.timestamp).size(14.0));
-                            Self::highlight_text(
-                                ui,
-                                &item.headers,
-                                &item.highlights,
-                                HighlightField::Header,
+            let group_rpc = self.gui_state.group_rpc;
+            let newest_first = self.gui_state.newest_first;
+
+            // Home/End jump to the top/bottom, unless e.g. editing the filter.
+            if !ctx.wants_keyboard_input() {
+                ctx.input(|i| {
+                    if i.key_pressed(Key::Home) {
+                        self.gui_state.scroll_to = Some(ScrollTarget::Top);
+                    } else if i.key_pressed(Key::End) {
+                        self.gui_state.scroll_to = Some(ScrollTarget::Bottom);
+                    }
+                });
+            }
+            // Following the tail means keeping the newest message, at whichever end, in view.
+            let target = match self.gui_state.scroll_to.take() {
+                None if self.gui_state.follow_tail && newest_first => Some(ScrollTarget::Top),
+                target => target,
+            };
+
+            let columns: Vec<DataColumn> = self
+                .gui_state
+                .table_columns
+                .iter()
+                .filter(|c| c.visible)
+                .map(|c| c.column)
+                .collect();
+            let labels: Vec<String> = columns
+                .iter()
+                .map(|c| c.label(&self.gui_state.projection_columns))
+                .collect();
+
+            let mut table = TableBuilder::new(ui)
+                // Widths are remembered per layout; a changed layout starts from the saved widths.
+                .id_salt((self.gui_state.connection_parameters.profile_key(), &columns))
+                .striped(false)
+                .resizable(true)
+                .sense(egui::Sense::click())
+                .cell_layout(Layout::left_to_right(Align::Center))
+                .column(Column::exact(36.0).resizable(false))
+                .column(Column::exact(20.0).resizable(false))
+                .column(Column::exact(20.0).resizable(false))
+                .auto_shrink(false)
+                .max_scroll_height(f32::INFINITY)
+                .stick_to_bottom(self.gui_state.follow_tail && !newest_first);
+            for (index, column) in self
+                .gui_state
+                .table_columns
+                .iter()
+                .filter(|c| c.visible)
+                .enumerate()
+            {
+                table = table.column(if index + 1 == columns.len() {
+                    Column::remainder().clip(true)
+                } else {
+                    Column::initial(column.width).at_least(24.0).clip(true)
+                });
+            }
+            match target {
+                Some(ScrollTarget::Top) => table = table.vertical_scroll_offset(0.0),
+                Some(ScrollTarget::Bottom) => table = table.vertical_scroll_offset(f32::MAX),
+                None => (),
+            }
+
+            // Only the rows in view are laid out, so first work out which there are.
+            let mut rows = Vec::new();
+            let len = self.gui_data.data.len();
+            for position in 0..len {
+                let index = if newest_first {
+                    len - 1 - position
+                } else {
+                    position
+                };
+                let item = &self.gui_data.data[index];
+                // Omit rows that should be filtered according to the current filter.
+                if !item.matches_filter {
+                    continue;
+                }
+                // When grouping, replies are shown beneath their request instead,
+                // provided that the request is itself shown.
+                if group_rpc {
+                    if let Some(Rpc::Reply { request, .. }) = item.rpc {
+                        if self
+                            .gui_data
+                            .get(request)
+                            .is_some_and(|request| request.matches_filter)
+                        {
+                            continue;
+                        }
+                    }
+                }
+                let reply = match item.rpc {
+                    Some(Rpc::Request {
+                        reply: Some((reply, _)),
+                    }) if group_rpc => self.gui_data.get(reply),
+                    _ => None,
+                };
+                for (item, nested) in [(Some(item), false), (reply, true)] {
+                    let Some(item) = item else { continue };
+                    rows.push(DisplayRow {
+                        id: item.id,
+                        height: ROW_HEIGHT,
+                        nested,
+                        trees: false,
+                    });
+                    if item.trees.is_some() {
+                        rows.push(DisplayRow {
+                            id: item.id,
+                            height: item.expanded_height,
+                            nested,
+                            trees: true,
+                        });
+                    }
+                }
+            }
+            if self.gui_state.filter_state.scroll_to_match {
+                let current = self.gui_state.filter_state.current_match.map(|(id, _)| id);
+                if let Some(index) = rows.iter().position(|r| Some(r.id) == current) {
+                    table = table.scroll_to_row(index, Some(Align::Center));
+                }
+            }
+
+            let mut widths = Vec::new();
+            let mut action = None;
+            let output = table
+                .header(ROW_HEIGHT, |mut header| {
+                    header.col(|_| ());
+                    header.col(|_| ());
+                    header.col(|_| ());
+                    for (column, label) in columns.iter().zip(&labels) {
+                        header.col(|ui| self.sort_heading(ui, label, *column));
+                    }
+                })
+                .body(|body| {
+                    widths = body.widths().to_vec();
+                    body.heterogeneous_rows(rows.iter().map(|r| r.height), |row| {
+                        let display = &rows[row.index()];
+                        let Some(item) = self.gui_data.get_mut(display.id) else {
+                            return;
+                        };
+                        if display.trees {
+                            Self::trees_row(
+                                row,
+                                item,
+                                &self.gui_state,
+                                &columns,
+                                &self.queue_bindings,
+                            );
+                        } else {
+                            Self::summary_row(
+                                row,
+                                item,
+                                &self.gui_state,
+                                &columns,
+                                display.nested,
+                                &mut action,
                             );
-                            ui.end_row();
-
-                            if item.expanded {
-                                ui.label("");
-                                ui.label("");
-                                Self::highlight_text(
-                                    ui,
-                                    &item.body,
-                                    &item.highlights,
-                                    HighlightField::Body,
-                                );
-                                ui.end_row();
-                            }
                         }
-                    })
-            });
+                    });
+                });
+
+            self.gui_state.filter_state.scroll_to_match = false;
+            match action {
+                Some(RowAction::SelectForDiff(id)) => self.gui_state.diff_selection = Some(id),
+                Some(RowAction::Diff(left, right)) => {
+                    self.gui_state.diff_items = Some((left, right))
+                }
+                Some(RowAction::ToggleBookmark(id)) => {
+                    self.gui_data.toggle_bookmark(id, &self.gui_state)
+                }
+                None => (),
+            }
+
+            // Remember resized widths, except that of the last column which takes the remainder.
+            for (column, width) in self
+                .gui_state
+                .table_columns
+                .iter_mut()
+                .filter(|c| c.visible)
+                .zip(widths.iter().skip(3))
+                .take(columns.len().saturating_sub(1))
+            {
+                column.width = *width;
+            }
+
+            // Scrolling away from the newest message disengages following.
+            let max_offset = (output.content_size.y - output.inner_rect.height()).max(0.0);
+            let offset = output.state.offset.y;
+            let at_newest = if newest_first {
+                offset <= 0.5
+            } else {
+                offset >= max_offset - 0.5
+            };
+            if !at_newest {
+                self.gui_state.follow_tail = false;
+            }
+        });
+    }
+
+    /// The RPC indicator for a message, if it is part of a request/reply exchange.
+    fn rpc_indicator(ui: &mut Ui, item: &ModelItem, timeout: f32) {
+        let (glyph, colour, tooltip) = match &item.rpc {
+            None => {
+                ui.label("");
+                return;
+            }
+            Some(Rpc::Request {
+                reply: Some((_, latency)),
+            }) => (
+                icon::ARROWS_LEFT_RIGHT,
+                Color32::LIGHT_GREEN,
+                format!("Request; replied in {} ms", latency.num_milliseconds()),
+            ),
+            Some(Rpc::Request { reply: None }) => {
+                let waited = chrono::Local::now() - item.received;
+                if waited.as_seconds_f32() > timeout {
+                    (
+                        icon::WARNING,
+                        Color32::RED,
+                        format!("Request; no reply within {} s", timeout),
+                    )
+                } else {
+                    (
+                        icon::HOURGLASS,
+                        Color32::GRAY,
+                        "Request; awaiting reply".to_string(),
+                    )
+                }
+            }
+            Some(Rpc::Reply { latency, .. }) => (
+                icon::ARROW_BEND_DOWN_LEFT,
+                Color32::LIGHT_BLUE,
+                format!("Reply, after {} ms", latency.num_milliseconds()),
+            ),
+        };
+        ui.label(RichText::new(glyph).color(colour))
+            .on_hover_text(tooltip);
+    }
+
+    /// Star which toggles whether a message is bookmarked, revealing its note (if any) on hover.
+    fn bookmark_button(ui: &mut Ui, item: &ModelItem, action: &mut Option<RowAction>) {
+        let colour = if item.bookmarked {
+            Color32::GOLD
+        } else {
+            Color32::DARK_GRAY
+        };
+        let response = ui.add(
+            egui::Button::new(RichText::new(icon::STAR).color(colour)).fill(Color32::TRANSPARENT),
+        );
+        let response = match (item.bookmarked, item.note.is_empty()) {
+            (_, false) => response.on_hover_text(&item.note),
+            (true, true) => response.on_hover_text("Remove bookmark"),
+            (false, true) => response.on_hover_text("Bookmark"),
+        };
+        if response.clicked() {
+            *action = Some(RowAction::ToggleBookmark(item.id));
+        }
+    }
+
+    /// The summary row of a message. Nested rows are replies displayed beneath their request.
+    fn summary_row(
+        mut row: TableRow,
+        item: &mut ModelItem,
+        gui_state: &GuiState,
+        columns: &[DataColumn],
+        nested: bool,
+        action: &mut Option<RowAction>,
+    ) {
+        let current = current_match(gui_state, item);
+        let caret = if item.expanded {
+            icon::CARET_DOWN
+        } else {
+            icon::CARET_RIGHT
+        };
+        row.col(|ui| {
+            if nested {
+                ui.add_space(12.0);
+            }
+            if ui
+                .add(egui::Button::new(RichText::new(caret)).fill(Color32::TRANSPARENT))
+                .clicked()
+            {
+                item.toggle_expanded();
+            }
         });
+        row.col(|ui| Self::rpc_indicator(ui, item, gui_state.rpc_timeout_secs));
+        row.col(|ui| Self::bookmark_button(ui, item, action));
+        for column in columns {
+            row.col(|ui| Self::data_cell(ui, item, *column, current));
+        }
+        row.response().context_menu(|ui| {
+            if ui.button("Select for comparison").clicked() {
+                *action = Some(RowAction::SelectForDiff(item.id));
+                ui.close_menu();
+            }
+            if let Some(selected) = gui_state.diff_selection.filter(|s| *s != item.id) {
+                if ui.button("Compare with selected").clicked() {
+                    *action = Some(RowAction::Diff(selected, item.id));
+                    ui.close_menu();
+                }
+            }
+        });
+    }
+
+    /// The header and body trees of an expanded message, which occupy the last column.
+    fn trees_row(
+        mut row: TableRow,
+        item: &mut ModelItem,
+        gui_state: &GuiState,
+        columns: &[DataColumn],
+        bindings: &[Binding],
+    ) {
+        let current = current_match(gui_state, item);
+        // The tree node holding the current match, in whichever field it is.
+        let current_path = |field| {
+            let highlight = item.highlights.get(current?)?;
+            let text = match highlight.field {
+                HighlightField::Header => &item.headers,
+                HighlightField::Body => &item.body,
+            };
+            json_path_at(text, highlight.start).filter(|_| highlight.field == field)
+        };
+        let current_header = current_path(HighlightField::Header);
+        let current_body = current_path(HighlightField::Body);
+        if let Some((headers, body_tree)) = &item.trees {
+            let filter = &gui_state.filter_state;
+            let regex = filter.regex.as_ref();
+            for _ in 0..2 + columns.len() {
+                row.col(|_| ());
+            }
+            let (rect, _) = row.col(|ui| {
+                ui.vertical(|ui| {
+                    ui.add(
+                        egui::TextEdit::multiline(&mut item.note)
+                            .hint_text("Note")
+                            .desired_rows(1),
+                    );
+                    if !item.info.bindings.is_empty() {
+                        let matched: Vec<String> = item
+                            .info
+                            .bindings
+                            .iter()
+                            .map(|id| match bindings.iter().find(|b| b.id == *id) {
+                                Some(b) => format!("{} / {}", b.exchange, b.routing_key),
+                                None => "(removed)".into(),
+                            })
+                            .collect();
+                        ui.label(
+                            RichText::new(format!("Matched bindings: {}", matched.join(", ")))
+                                .color(Color32::GRAY),
+                        );
+                    }
+                    json_tree(
+                        ui,
+                        ("headers", item.id),
+                        headers,
+                        regex.filter(|_| filter.filter_headers),
+                        &gui_state.highlight_rules,
+                        current_header.as_deref(),
+                    );
+                    ui.separator();
+                    match body_tree {
+                        Some(body) => json_tree(
+                            ui,
+                            ("body", item.id),
+                            body,
+                            regex.filter(|_| filter.filter_body),
+                            &gui_state.highlight_rules,
+                            current_body.as_deref(),
+                        ),
+                        None => Self::highlight_text(
+                            ui,
+                            &item.body,
+                            &item.highlights,
+                            &item.rule_highlights,
+                            HighlightField::Body,
+                            current,
+                            true,
+                        ),
+                    }
+                });
+            });
+            // Rows must be given their height up front, so use that measured this frame
+            // next time.
+            item.expanded_height = rect.height().max(ROW_HEIGHT);
+        }
+    }
+
+    /// The summary of a message for a single column, truncated to fit.
+    fn data_cell(ui: &mut Ui, item: &ModelItem, column: DataColumn, current: Option<usize>) {
+        let text = match column {
+            DataColumn::Headers => {
+                return Self::highlight_text(
+                    ui,
+                    &item.headers,
+                    &item.highlights,
+                    &item.rule_highlights,
+                    HighlightField::Header,
+                    current,
+                    false,
+                )
+            }
+            DataColumn::Body => {
+                return Self::highlight_text(
+                    ui,
+                    &item.body,
+                    &item.highlights,
+                    &item.rule_highlights,
+                    HighlightField::Body,
+                    current,
+                    false,
+                )
+            }
+            DataColumn::Timestamp => item.timestamp.clone(),
+            DataColumn::Exchange => item.info.exchange.clone(),
+            DataColumn::RoutingKey => item.info.routing_key.clone(),
+            DataColumn::ContentType => item.info.content_type.clone().unwrap_or_default(),
+            DataColumn::Size => item.info.size.to_string(),
+            DataColumn::MessageId => item.info.message_id.clone().unwrap_or_default(),
+            DataColumn::Projection(index) => {
+                item.projections.get(index).cloned().unwrap_or_default()
+            }
+        };
+        ui.add(egui::Label::new(RichText::new(text).size(14.0)).truncate());
     }
 
+    /// Text marked with the filter's highlights of the given field (the current one, an index
+    /// into `highlights`, distinctly) over those of the highlight rules.
     fn highlight_text(
         ui: &mut Ui,
         text: &str,
         highlights: &[Highlight],
+        rule_highlights: &[RuleHighlight],
         field_specifier: HighlightField,
+        current: Option<usize>,
+        wrap: bool,
     ) {
-        let mut job = LayoutJob::default();
-        let mut index = 0;
+        let rule_spans = rule_highlights
+            .iter()
+            .filter(|h| h.field == field_specifier)
+            .map(|h| (h.start, h.end, highlight_format(h.colour)));
+        let filter_spans = highlights
+            .iter()
+            .enumerate()
+            .filter(|(_, h)| h.field == field_specifier)
+            .map(|(index, h)| {
+                let colour = if Some(index) == current {
+                    Color32::GOLD
+                } else {
+                    Color32::LIGHT_BLUE
+                };
+                (h.start, h.end, highlight_format(colour))
+            });
+        let spans: Vec<_> = rule_spans.chain(filter_spans).collect();
+        let label = egui::Label::new(layered_highlight_job(text, &spans));
+        ui.add(if wrap { label.wrap() } else { label.truncate() });
+    }
+}
 
-        let text_format_regular = TextFormat {
-            color: Color32::WHITE,
-            ..Default::default()
-        };
-        let text_format_highlighted = TextFormat {
-            color: Color32::BLACK,
-            background: Color32::LIGHT_BLUE,
-            ..Default::default()
-        };
+/// Index of the current match among the item's highlights, if it holds it.
+fn current_match(gui_state: &GuiState, item: &ModelItem) -> Option<usize> {
+    gui_state
+        .filter_state
+        .current_match
+        .filter(|(id, _)| *id == item.id)
+        .map(|(_, index)| index)
+}
 
-        for highlight in highlights {
-            if highlight.field != field_specifier {
-                continue;
-            }
-            // Add normal text before the highlight
-            if index < highlight.start {
-                job.append(
-                    &text[index..highlight.start],
-                    0.0,
-                    text_format_regular.clone(),
-                );
-            }
-
-            // Add highlighted text
-            job.append(
-                &text[highlight.start..highlight.end],
-                0.0,
-                text_format_highlighted.clone(),
-            );
-            index = highlight.end;
-        }
+pub(crate) fn highlight_format(background: Color32) -> TextFormat {
+    TextFormat {
+        color: Color32::BLACK,
+        background,
+        ..Default::default()
+    }
+}
+
+/// Lay out `text` with each of the given byte ranges in its own format. Ranges may overlap,
+/// in which case the last takes precedence.
+pub(crate) fn layered_highlight_job(text: &str, spans: &[(usize, usize, TextFormat)]) -> LayoutJob {
+    let mut job = LayoutJob::default();
+    let text_format_regular = TextFormat {
+        color: Color32::WHITE,
+        ..Default::default()
+    };
 
-        // Add remaining text after the last highlight
-        if index < text.len() {
-            job.append(&text[index..], 0.0, text_format_regular.clone());
+    let mut boundaries: Vec<usize> = spans
+        .iter()
+        .flat_map(|(start, end, _)| [*start, *end])
+        .chain([0, text.len()])
+        .collect();
+    boundaries.sort_unstable();
+    boundaries.dedup();
+
+    // Sweep through the segments in order, keeping the spans begun so far by precedence;
+    // the topmost which has not yet ended covers the whole of the current segment.
+    let mut by_start: Vec<usize> = (0..spans.len()).collect();
+    by_start.sort_by_key(|index| spans[*index].0);
+    let mut by_start = by_start.into_iter().peekable();
+    let mut active = BinaryHeap::new();
+    for segment in boundaries.windows(2) {
+        let (start, end) = (segment[0], segment[1]);
+        while let Some(index) = by_start.next_if(|index| spans[*index].0 <= start) {
+            active.push(index);
+        }
+        while active.peek().is_some_and(|index| spans[*index].1 <= start) {
+            active.pop();
         }
-        ui.add(egui::Label::new(job).wrap());
+        let format = active
+            .peek()
+            .map_or(&text_format_regular, |index| &spans[*index].2);
+        job.append(&text[start..end], 0.0, format.clone());
+    }
+    job
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn backgrounds(job: &LayoutJob) -> Vec<(&str, Color32)> {
+        job.sections
+            .iter()
+            .map(|s| (&job.text[s.byte_range.clone()], s.format.background))
+            .collect()
+    }
+
+    #[test]
+    fn later_spans_take_precedence_where_they_overlap() {
+        let spans = [
+            (0, 9, highlight_format(Color32::RED)),
+            (2, 4, highlight_format(Color32::GREEN)),
+            (3, 6, highlight_format(Color32::BLUE)),
+            (5, 5, highlight_format(Color32::GOLD)),
+        ];
+        let job = layered_highlight_job("abcdefghij", &spans);
+        assert_eq!(
+            backgrounds(&job),
+            vec![
+                ("ab", Color32::RED),
+                ("c", Color32::GREEN),
+                ("d", Color32::BLUE),
+                ("e", Color32::BLUE),
+                ("f", Color32::BLUE),
+                ("ghi", Color32::RED),
+                ("j", Color32::TRANSPARENT),
+            ]
+        );
+    }
+
+    #[test]
+    fn earlier_spans_show_through_after_later_ones_end() {
+        let spans = [
+            (4, 6, highlight_format(Color32::GREEN)),
+            (0, 8, highlight_format(Color32::RED)),
+            (1, 2, highlight_format(Color32::BLUE)),
+        ];
+        let job = layered_highlight_job("abcdefgh", &spans);
+        assert_eq!(
+            backgrounds(&job),
+            vec![
+                ("a", Color32::RED),
+                ("b", Color32::BLUE),
+                ("cd", Color32::RED),
+                ("ef", Color32::RED),
+                ("gh", Color32::RED),
+            ]
+        );
     }
 }
@@ -0,0 +1,268 @@
+use lapin::types::{AMQPValue, ByteArray, DecimalValue, FieldArray, FieldTable, ShortString};
+use serde_json::json;
+
+/// Render a field table as plain JSON for display and filtering.
+/// Type information is discarded; see `field_table_to_typed_json` for a lossless form.
+pub(crate) fn field_table_to_json(field_table: &FieldTable) -> serde_json::Value {
+    let mut json_map = serde_json::Map::new();
+
+    for (key, value) in field_table.inner().iter() {
+        json_map.insert(key.to_string(), amqp_value_to_json(value));
+    }
+
+    serde_json::Value::Object(json_map)
+}
+
+pub(crate) fn amqp_value_to_json(value: &AMQPValue) -> serde_json::Value {
+    match value {
+        AMQPValue::Void => json!(null),
+        AMQPValue::ShortShortInt(i) => json!(i),
+        AMQPValue::ShortShortUInt(u) => json!(u),
+        AMQPValue::ShortInt(i) => json!(i),
+        AMQPValue::ShortUInt(u) => json!(u),
+        AMQPValue::LongInt(i) => json!(i),
+        AMQPValue::LongUInt(u) => json!(u),
+        AMQPValue::LongLongInt(i) => json!(i),
+        AMQPValue::LongString(ls) => json!(ls.to_string()),
+        AMQPValue::ShortString(ss) => json!(ss.to_string()),
+        AMQPValue::Boolean(b) => json!(b),
+        AMQPValue::Float(f) => json!(f),
+        AMQPValue::Double(d) => json!(d),
+        AMQPValue::DecimalValue(d) => json!({"scale": d.scale, "value": d.value}),
+        AMQPValue::Timestamp(ts) => json!({"epoch": ts, "iso8601": format_timestamp(*ts)}),
+        AMQPValue::ByteArray(bytes) => json!(to_hex(bytes.as_slice())),
+        AMQPValue::FieldArray(arr) => {
+            serde_json::Value::Array(arr.as_slice().iter().map(amqp_value_to_json).collect())
+        }
+        AMQPValue::FieldTable(ft) => field_table_to_json(ft),
+    }
+}
+
+/// AMQP timestamps are seconds since the unix epoch.
+fn format_timestamp(ts: u64) -> String {
+    i64::try_from(ts)
+        .ok()
+        .and_then(|secs| chrono::DateTime::from_timestamp(secs, 0))
+        .map(|dt| dt.to_rfc3339_opts(chrono::SecondsFormat::Secs, true))
+        .unwrap_or_else(|| "out of range".into())
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(hex: &str) -> Result<Vec<u8>, String> {
+    if !hex.len().is_multiple_of(2) {
+        return Err(format!("Odd length hex string '{}'", hex));
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            hex.get(i..i + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                .ok_or_else(|| format!("Invalid hex string '{}'", hex))
+        })
+        .collect()
+}
+
+/// Render a field table as JSON in which every value is tagged with its AMQP type,
+/// e.g. `{"x-count": {"type": "LongLongInt", "value": 1}}`, so that it can be
+/// converted back into an identical `FieldTable` with `typed_json_to_field_table`.
+pub(crate) fn field_table_to_typed_json(field_table: &FieldTable) -> serde_json::Value {
+    let mut json_map = serde_json::Map::new();
+
+    for (key, value) in field_table.inner().iter() {
+        json_map.insert(key.to_string(), amqp_value_to_typed_json(value));
+    }
+
+    serde_json::Value::Object(json_map)
+}
+
+pub(crate) fn amqp_value_to_typed_json(value: &AMQPValue) -> serde_json::Value {
+    let (t, v) = match value {
+        AMQPValue::Void => ("Void", json!(null)),
+        AMQPValue::ShortShortInt(i) => ("ShortShortInt", json!(i)),
+        AMQPValue::ShortShortUInt(u) => ("ShortShortUInt", json!(u)),
+        AMQPValue::ShortInt(i) => ("ShortInt", json!(i)),
+        AMQPValue::ShortUInt(u) => ("ShortUInt", json!(u)),
+        AMQPValue::LongInt(i) => ("LongInt", json!(i)),
+        AMQPValue::LongUInt(u) => ("LongUInt", json!(u)),
+        AMQPValue::LongLongInt(i) => ("LongLongInt", json!(i)),
+        AMQPValue::LongString(ls) => ("LongString", json!(ls.to_string())),
+        AMQPValue::ShortString(ss) => ("ShortString", json!(ss.to_string())),
+        AMQPValue::Boolean(b) => ("Boolean", json!(b)),
+        AMQPValue::Float(f) => ("Float", json!(f)),
+        AMQPValue::Double(d) => ("Double", json!(d)),
+        AMQPValue::DecimalValue(d) => ("DecimalValue", json!({"scale": d.scale, "value": d.value})),
+        AMQPValue::Timestamp(ts) => ("Timestamp", json!(ts)),
+        AMQPValue::ByteArray(bytes) => ("ByteArray", json!(to_hex(bytes.as_slice()))),
+        AMQPValue::FieldArray(arr) => (
+            "FieldArray",
+            serde_json::Value::Array(
+                arr.as_slice()
+                    .iter()
+                    .map(amqp_value_to_typed_json)
+                    .collect(),
+            ),
+        ),
+        AMQPValue::FieldTable(ft) => ("FieldTable", field_table_to_typed_json(ft)),
+    };
+    json!({"type": t, "value": v})
+}
+
+/// Inverse of `field_table_to_typed_json`.
+pub(crate) fn typed_json_to_field_table(json: &serde_json::Value) -> Result<FieldTable, String> {
+    let map = json
+        .as_object()
+        .ok_or_else(|| format!("Expected an object, found '{}'", json))?;
+
+    let mut field_table = FieldTable::default();
+    for (key, value) in map {
+        field_table.insert(
+            ShortString::from(key.clone()),
+            typed_json_to_amqp_value(value)?,
+        );
+    }
+    Ok(field_table)
+}
+
+pub(crate) fn typed_json_to_amqp_value(json: &serde_json::Value) -> Result<AMQPValue, String> {
+    let t = json["type"]
+        .as_str()
+        .ok_or_else(|| format!("Missing AMQP type in '{}'", json))?;
+    let v = &json["value"];
+    let type_error = || format!("Invalid value '{}' for type '{}'", v, t);
+
+    fn int<T: TryFrom<i64>>(v: &serde_json::Value) -> Option<T> {
+        v.as_i64().and_then(|i| T::try_from(i).ok())
+    }
+    fn uint<T: TryFrom<u64>>(v: &serde_json::Value) -> Option<T> {
+        v.as_u64().and_then(|u| T::try_from(u).ok())
+    }
+
+    Ok(match t {
+        "Void" => AMQPValue::Void,
+        "ShortShortInt" => AMQPValue::ShortShortInt(int(v).ok_or_else(type_error)?),
+        "ShortShortUInt" => AMQPValue::ShortShortUInt(uint(v).ok_or_else(type_error)?),
+        "ShortInt" => AMQPValue::ShortInt(int(v).ok_or_else(type_error)?),
+        "ShortUInt" => AMQPValue::ShortUInt(uint(v).ok_or_else(type_error)?),
+        "LongInt" => AMQPValue::LongInt(int(v).ok_or_else(type_error)?),
+        "LongUInt" => AMQPValue::LongUInt(uint(v).ok_or_else(type_error)?),
+        "LongLongInt" => AMQPValue::LongLongInt(int(v).ok_or_else(type_error)?),
+        "LongString" => AMQPValue::LongString(v.as_str().ok_or_else(type_error)?.into()),
+        "ShortString" => AMQPValue::ShortString(v.as_str().ok_or_else(type_error)?.into()),
+        "Boolean" => AMQPValue::Boolean(v.as_bool().ok_or_else(type_error)?),
+        "Float" => AMQPValue::Float(v.as_f64().ok_or_else(type_error)? as f32),
+        "Double" => AMQPValue::Double(v.as_f64().ok_or_else(type_error)?),
+        "DecimalValue" => AMQPValue::DecimalValue(DecimalValue {
+            scale: uint(&v["scale"]).ok_or_else(type_error)?,
+            value: uint(&v["value"]).ok_or_else(type_error)?,
+        }),
+        "Timestamp" => AMQPValue::Timestamp(v.as_u64().ok_or_else(type_error)?),
+        "ByteArray" => AMQPValue::ByteArray(ByteArray::from(from_hex(
+            v.as_str().ok_or_else(type_error)?,
+        )?)),
+        "FieldArray" => AMQPValue::FieldArray(FieldArray::from(
+            v.as_array()
+                .ok_or_else(type_error)?
+                .iter()
+                .map(typed_json_to_amqp_value)
+                .collect::<Result<Vec<_>, _>>()?,
+        )),
+        "FieldTable" => AMQPValue::FieldTable(typed_json_to_field_table(v)?),
+        _ => return Err(format!("Unknown AMQP type '{}'", t)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lapin::types::LongString;
+
+    /// Round trip through the typed JSON as text, as when a subscription set is saved.
+    fn round_trip(field_table: &FieldTable) -> FieldTable {
+        let text = field_table_to_typed_json(field_table).to_string();
+        typed_json_to_field_table(&serde_json::from_str(&text).unwrap()).unwrap()
+    }
+
+    fn table(pairs: Vec<(&str, AMQPValue)>) -> FieldTable {
+        let mut table = FieldTable::default();
+        for (k, v) in pairs {
+            table.insert(k.into(), v);
+        }
+        table
+    }
+
+    #[test]
+    fn x_death_round_trips() {
+        let death = table(vec![
+            ("count", AMQPValue::LongLongInt(3)),
+            (
+                "reason",
+                AMQPValue::LongString(LongString::from("rejected")),
+            ),
+            ("queue", AMQPValue::ShortString("orders".into())),
+            ("time", AMQPValue::Timestamp(1_700_000_000)),
+            (
+                "routing-keys",
+                AMQPValue::FieldArray(FieldArray::from(vec![AMQPValue::LongString(
+                    "order.created".into(),
+                )])),
+            ),
+        ]);
+        let headers = table(vec![(
+            "x-death",
+            AMQPValue::FieldArray(FieldArray::from(vec![
+                AMQPValue::FieldTable(death.clone()),
+                AMQPValue::FieldTable(death),
+            ])),
+        )]);
+        assert_eq!(round_trip(&headers), headers);
+    }
+
+    #[test]
+    fn scalar_types_round_trip() {
+        let headers = table(vec![
+            (
+                "bytes",
+                AMQPValue::ByteArray(ByteArray::from(vec![0u8, 1, 0xab, 0xff])),
+            ),
+            ("empty-bytes", AMQPValue::ByteArray(ByteArray::from(vec![]))),
+            ("timestamp", AMQPValue::Timestamp(u64::MAX)),
+            (
+                "decimal",
+                AMQPValue::DecimalValue(DecimalValue {
+                    scale: 2,
+                    value: 12345,
+                }),
+            ),
+            ("float", AMQPValue::Float(0.1)),
+            ("negative-float", AMQPValue::Float(-3.75e-8)),
+            ("double", AMQPValue::Double(0.1)),
+            ("void", AMQPValue::Void),
+            ("bool", AMQPValue::Boolean(false)),
+            ("i8", AMQPValue::ShortShortInt(-128)),
+            ("u8", AMQPValue::ShortShortUInt(255)),
+            ("i16", AMQPValue::ShortInt(-1)),
+            ("u16", AMQPValue::ShortUInt(65535)),
+            ("i32", AMQPValue::LongInt(i32::MIN)),
+            ("u32", AMQPValue::LongUInt(u32::MAX)),
+            ("i64", AMQPValue::LongLongInt(i64::MIN)),
+        ]);
+        assert_eq!(round_trip(&headers), headers);
+    }
+
+    #[test]
+    fn invalid_typed_json_is_rejected() {
+        for json in [
+            json!({"a": {"type": "ByteArray", "value": "abc"}}),
+            json!({"a": {"type": "ByteArray", "value": "zz"}}),
+            json!({"a": {"type": "ShortShortUInt", "value": 256}}),
+            json!({"a": {"type": "Mystery", "value": 1}}),
+            json!({"a": {"value": 1}}),
+            json!([1]),
+        ] {
+            assert!(typed_json_to_field_table(&json).is_err(), "{}", json);
+        }
+    }
+}
@@ -0,0 +1,249 @@
+//! Local evaluation of AMQP binding semantics, so that deliveries can be attributed to
+//! the binding(s) that caused them to arrive.
+
+use lapin::types::{AMQPValue, FieldTable};
+
+use super::{Binding, ExchangeType};
+
+/// Whether `routing_key` matches the topic exchange `pattern`, where `*` matches exactly
+/// one dot separated word and `#` matches zero or more words. As for the broker, an empty
+/// routing key or pattern has no words at all.
+pub(crate) fn topic_matches(pattern: &str, routing_key: &str) -> bool {
+    fn words(s: &str) -> Vec<&str> {
+        match s {
+            "" => Vec::new(),
+            s => s.split('.').collect(),
+        }
+    }
+    let key = words(routing_key);
+    // Whether the pattern words so far match the first n words of the key, for each n; this
+    // takes time proportional to the product of the lengths, however many `#` there are.
+    let mut matched = vec![false; key.len() + 1];
+    matched[0] = true;
+    for word in words(pattern) {
+        if word == "#" {
+            for n in 1..=key.len() {
+                matched[n] |= matched[n - 1];
+            }
+        } else {
+            for n in (1..=key.len()).rev() {
+                matched[n] = matched[n - 1] && (word == "*" || word == key[n - 1]);
+            }
+            matched[0] = false;
+        }
+    }
+    matched[key.len()]
+}
+
+/// String types compare by content regardless of long/short encoding.
+fn values_equal(a: &AMQPValue, b: &AMQPValue) -> bool {
+    fn as_str(v: &AMQPValue) -> Option<String> {
+        match v {
+            AMQPValue::LongString(s) => Some(s.to_string()),
+            AMQPValue::ShortString(s) => Some(s.to_string()),
+            _ => None,
+        }
+    }
+    match (as_str(a), as_str(b)) {
+        (Some(a), Some(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Evaluate a headers exchange binding's arguments (including `x-match`) against the
+/// headers of a message.
+pub(crate) fn headers_match(arguments: &FieldTable, headers: Option<&FieldTable>) -> bool {
+    let x_match = match arguments.inner().get("x-match") {
+        Some(AMQPValue::LongString(s)) => s.to_string(),
+        Some(AMQPValue::ShortString(s)) => s.to_string(),
+        _ => "all".into(),
+    };
+    let include_x = x_match.ends_with("-with-x");
+    let any = x_match.starts_with("any");
+
+    // The broker never treats `x-match` itself as a criterion, even for the `-with-x` modes.
+    let mut criteria = arguments
+        .inner()
+        .iter()
+        .filter(|(k, _)| k.as_str() != "x-match")
+        .filter(|(k, _)| include_x || !k.as_str().starts_with("x-"))
+        .peekable();
+    if criteria.peek().is_none() {
+        // With no criteria RabbitMQ matches every message for `all`, and none for `any`.
+        return !any;
+    }
+
+    let mut results = criteria.map(|(k, v)| {
+        match headers.and_then(|h| h.inner().get(k.as_str())) {
+            None => false,
+            // A void argument only requires the header to be present.
+            Some(_) if *v == AMQPValue::Void => true,
+            Some(h) => values_equal(v, h),
+        }
+    });
+    if any {
+        results.any(|r| r)
+    } else {
+        results.all(|r| r)
+    }
+}
+
+impl Binding {
+    /// Whether this binding would route a message to our queue, according to the type of
+    /// its exchange.
+    pub(crate) fn matches(
+        &self,
+        exchange: &str,
+        routing_key: &str,
+        headers: Option<&FieldTable>,
+    ) -> bool {
+        if self.exchange != exchange {
+            return false;
+        }
+        match self.exchange_type {
+            ExchangeType::Direct => self.routing_key == routing_key,
+            ExchangeType::Fanout => true,
+            ExchangeType::Topic => topic_matches(&self.routing_key, routing_key),
+            ExchangeType::Headers => headers_match(&self.arguments, headers),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn arguments(pairs: &[(&str, AMQPValue)]) -> FieldTable {
+        let mut table = FieldTable::default();
+        for (k, v) in pairs {
+            table.insert((*k).into(), v.clone());
+        }
+        table
+    }
+
+    fn string(s: &str) -> AMQPValue {
+        AMQPValue::LongString(s.into())
+    }
+
+    #[test]
+    fn topic_patterns_match_words() {
+        let cases = [
+            ("a.b.c", "a.b.c", true),
+            ("a.b.c", "a.b", false),
+            ("a.*.c", "a.b.c", true),
+            ("a.*.c", "a.c", false),
+            ("*", "a", true),
+            ("*", "", false),
+            ("*", "a.b", false),
+            ("#", "", true),
+            ("#", "a.b.c", true),
+            ("a.#", "a", true),
+            ("a.#", "a.b.c", true),
+            ("a.#", "b.a", false),
+            ("#.c", "a.b.c", true),
+            ("a.#.c", "a.c", true),
+            ("a.#.c", "a.b.b.c", true),
+            ("a.#.c", "a.b.c.d", false),
+            ("#.*", "", false),
+            ("#.*", "a", true),
+            ("#.#.#", "a.b", true),
+            ("", "", true),
+            ("", "a", false),
+            ("a..b", "a..b", true),
+            ("a.*.b", "a..b", true),
+        ];
+        for (pattern, key, expected) in cases {
+            assert_eq!(
+                topic_matches(pattern, key),
+                expected,
+                "{} ~ {}",
+                pattern,
+                key
+            );
+        }
+    }
+
+    #[test]
+    fn many_hashes_match_quickly() {
+        let pattern = vec!["#"; 40].join(".") + ".x";
+        let key = vec!["a"; 40].join(".");
+        assert!(!topic_matches(&pattern, &key));
+        assert!(topic_matches(&pattern, &(key + ".x")));
+    }
+
+    #[test]
+    fn bindings_match_according_to_exchange_type() {
+        let binding = |exchange_type, routing_key: &str| Binding {
+            id: uuid::Uuid::new_v4(),
+            exchange: "events".into(),
+            exchange_type,
+            routing_key: routing_key.into(),
+            arguments: FieldTable::default(),
+        };
+        assert!(!binding(ExchangeType::Direct, "a.*").matches("events", "a.b", None));
+        assert!(binding(ExchangeType::Direct, "a.*").matches("events", "a.*", None));
+        assert!(binding(ExchangeType::Topic, "a.*").matches("events", "a.b", None));
+        assert!(binding(ExchangeType::Fanout, "a").matches("events", "b", None));
+        assert!(!binding(ExchangeType::Fanout, "").matches("other", "", None));
+        // A headers binding without x-match defaults to all, here with no criteria.
+        assert!(binding(ExchangeType::Headers, "a").matches("events", "b", None));
+    }
+
+    #[test]
+    fn no_criteria_matches_everything_for_all_and_nothing_for_any() {
+        let headers = arguments(&[("tenant", string("acme"))]);
+        let all = arguments(&[("x-match", string("all"))]);
+        let any = arguments(&[("x-match", string("any"))]);
+        assert!(headers_match(&all, Some(&headers)));
+        assert!(headers_match(&all, None));
+        assert!(!headers_match(&any, Some(&headers)));
+        assert!(!headers_match(&any, None));
+    }
+
+    #[test]
+    fn with_x_modes_do_not_treat_x_match_as_a_criterion() {
+        let headers = arguments(&[("x-tenant", string("acme"))]);
+        let all = arguments(&[
+            ("x-match", string("all-with-x")),
+            ("x-tenant", string("acme")),
+        ]);
+        let any = arguments(&[
+            ("x-match", string("any-with-x")),
+            ("x-tenant", string("acme")),
+        ]);
+        assert!(headers_match(&all, Some(&headers)));
+        assert!(headers_match(&any, Some(&headers)));
+        assert!(!headers_match(&any, None));
+    }
+
+    #[test]
+    fn x_headers_are_ignored_without_with_x() {
+        let binding = arguments(&[("x-match", string("all")), ("x-tenant", string("acme"))]);
+        assert!(headers_match(&binding, None));
+    }
+
+    #[test]
+    fn all_and_any_evaluate_criteria() {
+        let headers = arguments(&[("tenant", string("acme")), ("region", string("eu"))]);
+        let all = arguments(&[
+            ("x-match", string("all")),
+            ("tenant", AMQPValue::ShortString("acme".into())),
+            ("region", string("us")),
+        ]);
+        let any = arguments(&[
+            ("x-match", string("any")),
+            ("tenant", string("acme")),
+            ("region", string("us")),
+        ]);
+        assert!(!headers_match(&all, Some(&headers)));
+        assert!(headers_match(&any, Some(&headers)));
+    }
+
+    #[test]
+    fn void_argument_requires_presence_only() {
+        let binding = arguments(&[("x-match", string("all")), ("tenant", AMQPValue::Void)]);
+        let headers = arguments(&[("tenant", string("anything"))]);
+        assert!(headers_match(&binding, Some(&headers)));
+        assert!(!headers_match(&binding, None));
+    }
+}
@@ -1,38 +1,121 @@
 use eframe::egui;
 use futures_lite::StreamExt;
 use lapin::{
-    options::{BasicConsumeOptions, QueueBindOptions, QueueDeclareOptions},
+    options::{BasicCancelOptions, BasicConsumeOptions, QueueBindOptions, QueueDeclareOptions},
     types::{AMQPValue, FieldTable},
     uri::AMQPUri,
     Channel, Connection, ConnectionProperties, Consumer, Queue,
 };
 
-use serde_json::json;
 use tokio::sync::mpsc;
 use uuid::Uuid;
 
+pub(crate) mod field_table;
+pub(crate) mod matching;
+pub(crate) use field_table::field_table_to_json;
+
 /// Carries commands from the UI to the rabbit connection manager
 pub enum ConnectionCommand {
     // Used to pass the Egui repaint signaller
     Disconnect,
     Connect(AMQPUri, egui::Context),
     Bind {
+        id: Uuid,
         exchange: String,
+        exchange_type: ExchangeType,
         routing_key: String,
         options: QueueBindOptions,
         arguments: FieldTable,
     },
     Unbind(Binding),
+    /// Replace the binding with the same id by this one, without a gap in between.
+    Rebind(Binding),
+    /// Cancel the consumer, leaving messages to accumulate on the (still bound) queue
+    PauseConsumer,
+    /// Resume consuming after a `PauseConsumer`
+    ResumeConsumer,
+}
+
+/// The kind of exchange, which determines how bindings are interpreted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum ExchangeType {
+    Direct,
+    Fanout,
+    #[default]
+    Topic,
+    Headers,
+}
+
+impl ExchangeType {
+    pub const ALL: [Self; 4] = [Self::Direct, Self::Fanout, Self::Topic, Self::Headers];
+}
+
+impl std::fmt::Display for ExchangeType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Direct => "Direct",
+                Self::Fanout => "Fanout",
+                Self::Topic => "Topic",
+                Self::Headers => "Headers",
+            }
+        )
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct Binding {
     pub id: Uuid,
     pub exchange: String,
+    /// The type of the exchange, as given on subscribing, which determines how the routing
+    /// key and arguments are matched.
+    pub exchange_type: ExchangeType,
     pub routing_key: String,
     pub arguments: FieldTable,
 }
 
+impl Binding {
+    /// A binding to as much of what is published to the exchange as its type allows: an
+    /// empty routing key for fanout (which ignores it) and direct (which catches only
+    /// messages published with an empty key), `#` for topic, and an `x-match all` with no
+    /// criteria for headers (which the broker matches against every message).
+    pub fn wildcard(exchange: &str, exchange_type: ExchangeType) -> Self {
+        let mut arguments = FieldTable::default();
+        let routing_key = match exchange_type {
+            ExchangeType::Direct | ExchangeType::Fanout => "",
+            ExchangeType::Topic => "#",
+            ExchangeType::Headers => {
+                arguments.insert("x-match".into(), AMQPValue::LongString("all".into()));
+                ""
+            }
+        };
+        Self {
+            id: Uuid::new_v4(),
+            exchange: exchange.into(),
+            exchange_type,
+            routing_key: routing_key.into(),
+            arguments,
+        }
+    }
+}
+
+/// Envelope and property information accompanying a delivery.
+#[derive(Clone, Debug, Default)]
+pub struct DeliveryInfo {
+    pub exchange: String,
+    pub routing_key: String,
+    pub content_type: Option<String>,
+    pub correlation_id: Option<String>,
+    pub reply_to: Option<String>,
+    pub message_id: Option<String>,
+    /// Size of the message body in bytes
+    pub size: usize,
+    /// Ids of the bindings which (we infer) routed this message to our queue
+    pub bindings: Vec<Uuid>,
+}
+
 /// Carries messages from the connection/connection manager to UI.
 /// This comprises both rabbit data payloads and status changes.
 pub enum ConnectionUpdate {
@@ -42,15 +125,20 @@ pub enum ConnectionUpdate {
     TextDelivery {
         headers: String,
         content: String,
-        content_type: Option<String>,
+        info: DeliveryInfo,
     },
     /// A binary message, with content redacted
     BinaryDelivery {
         headers: String,
-        content_type: Option<String>,
+        info: DeliveryInfo,
     },
     Bound(Binding),
     Unbound(Binding),
+    /// The binding with the same id has been replaced by this one.
+    Rebound(Binding),
+    /// A `Rebind` was not made, there being no binding with the same id (e.g. because it
+    /// has meanwhile been disabled or deleted).
+    RebindFailed(Binding),
 }
 
 pub struct ConnectionManager {
@@ -82,10 +170,27 @@ impl ConnectionManager {
             .expect("Internal channel closed");
     }
 
+    pub fn rebind(&self, binding: Binding) {
+        self.tx
+            .send(ConnectionCommand::Rebind(binding))
+            .expect("Internal channel closed");
+    }
+
+    pub fn set_consuming(&self, consuming: bool) {
+        let command = if consuming {
+            ConnectionCommand::ResumeConsumer
+        } else {
+            ConnectionCommand::PauseConsumer
+        };
+        self.tx.send(command).expect("Internal channel closed");
+    }
+
     pub fn bind(&self, binding: Binding) {
         self.tx
             .send(crate::rabbit::ConnectionCommand::Bind {
+                id: binding.id,
                 exchange: binding.exchange,
+                exchange_type: binding.exchange_type,
                 routing_key: binding.routing_key,
                 options: QueueBindOptions::default(),
                 arguments: binding.arguments,
@@ -94,42 +199,6 @@ impl ConnectionManager {
     }
 }
 
-pub(crate) fn field_table_to_json(field_table: &FieldTable) -> serde_json::Value {
-    let mut json_map = serde_json::Map::new();
-
-    for (key, value) in field_table.inner().iter() {
-        let json_value = match value {
-            AMQPValue::Void => json!(null),
-            AMQPValue::ShortShortInt(i) => json!(i),
-            AMQPValue::ShortShortUInt(u) => json!(u),
-            AMQPValue::ShortInt(i) => json!(i),
-            AMQPValue::ShortUInt(u) => json!(u),
-            AMQPValue::LongInt(i) => json!(i),
-            AMQPValue::LongUInt(u) => json!(u),
-            AMQPValue::LongLongInt(i) => json!(i),
-            AMQPValue::LongString(ls) => json!(ls.to_string()),
-            AMQPValue::ShortString(ss) => json!(ss.to_string()),
-            AMQPValue::Boolean(b) => json!(b),
-            AMQPValue::Float(f) => json!(f),
-            AMQPValue::Double(d) => json!(d),
-            AMQPValue::DecimalValue(d) => json!({"scale": d.scale, "value": d.value}),
-            AMQPValue::Timestamp(ts) => json!(ts), // TODO consider formatting timestamps
-            AMQPValue::ByteArray(bytes) => json!(bytes),
-            AMQPValue::FieldArray(arr) => {
-                json!(arr
-                    .as_slice()
-                    .iter()
-                    .map(|v| format!("{:?}", v))
-                    .collect::<Vec<_>>())
-            }
-            AMQPValue::FieldTable(ft) => field_table_to_json(ft),
-        };
-        json_map.insert(key.to_string(), json_value);
-    }
-
-    serde_json::Value::Object(json_map)
-}
-
 async fn connection_manager_task(
     mut rx: mpsc::UnboundedReceiver<ConnectionCommand>,
     tx: mpsc::UnboundedSender<ConnectionUpdate>,
@@ -137,13 +206,20 @@ async fn connection_manager_task(
     let options =
         ConnectionProperties::default().with_executor(tokio_executor_trait::Tokio::current());
 
+    // Not auto-delete, since the queue must survive the consumer being cancelled whilst
+    // paused; being exclusive, it is deleted when the connection closes regardless.
     let queue_declare_options = QueueDeclareOptions {
         passive: false,
         durable: false,
-        auto_delete: true,
+        auto_delete: false,
         exclusive: true,
         nowait: false,
     };
+    let consume_options = BasicConsumeOptions {
+        exclusive: true,
+        no_ack: true,
+        ..Default::default()
+    };
 
     // Need to restructure into an outer loop that waits on connection requests, and an inner loop that consumes from the queue
     // and services other messages.
@@ -153,6 +229,9 @@ async fn connection_manager_task(
         let channel: Channel;
         let queue: Queue;
         let egui_ctx: egui::Context;
+        // Bindings currently in place, used to attribute deliveries.
+        let mut bindings: Vec<Binding> = Vec::new();
+        let mut consuming = true;
 
         match rx.recv().await {
             Some(ConnectionCommand::Connect(uri, ctx)) => {
@@ -162,13 +241,13 @@ async fn connection_manager_task(
                 queue = channel
                     .queue_declare("", queue_declare_options.clone(), FieldTable::default())
                     .await?;
-                let opts = BasicConsumeOptions {
-                    exclusive: true,
-                    no_ack: true,
-                    ..Default::default()
-                };
                 consumer = channel
-                    .basic_consume(queue.name().as_str(), "", opts, FieldTable::default())
+                    .basic_consume(
+                        queue.name().as_str(),
+                        "",
+                        consume_options,
+                        FieldTable::default(),
+                    )
                     .await?;
                 egui_ctx = ctx;
 
@@ -195,8 +274,24 @@ async fn connection_manager_task(
                     Some(ConnectionCommand::Connect(_, _)) => {
                         log::warn!("Ignoring a 'connect' command whilst already connected");
                     }
+                    Some(ConnectionCommand::PauseConsumer) => {
+                        if consuming {
+                            channel.basic_cancel(consumer.tag().as_str(), BasicCancelOptions::default()).await?;
+                            consuming = false;
+                        }
+                    }
+                    Some(ConnectionCommand::ResumeConsumer) => {
+                        if !consuming {
+                            consumer = channel
+                                .basic_consume(queue.name().as_str(), "", consume_options, FieldTable::default())
+                                .await?;
+                            consuming = true;
+                        }
+                    }
                     Some(ConnectionCommand::Bind {
+                        id,
                         exchange,
+                        exchange_type,
                         routing_key,
                         options,
                         arguments,
@@ -209,13 +304,42 @@ async fn connection_manager_task(
                                 arguments.clone(),
                             )
                             .await?;
-                        tx.send(ConnectionUpdate::Bound(Binding {  exchange, routing_key, arguments, id: Uuid::new_v4() })).expect("Internal channel closed");
+                        let binding = Binding {  exchange, exchange_type, routing_key, arguments, id };
+                        bindings.push(binding.clone());
+                        tx.send(ConnectionUpdate::Bound(binding)).expect("Internal channel closed");
                     }
                     Some(ConnectionCommand::Unbind(binding) ) => {
                             channel.queue_unbind(queue.name().as_str(), &binding.exchange, &binding.routing_key, binding.arguments.clone())
                                 .await?;
+                            bindings.retain(|b| b.id != binding.id);
                             tx.send(ConnectionUpdate::Unbound(binding)).expect("Internal connection closed")
                     }
+                    Some(ConnectionCommand::Rebind(binding)) => {
+                        let Some(old) = bindings.iter_mut().find(|b| b.id == binding.id) else {
+                            log::warn!("Ignoring an edit of a binding which no longer exists");
+                            tx.send(ConnectionUpdate::RebindFailed(binding)).expect("Internal channel closed");
+                            continue;
+                        };
+                        // Bind before unbinding so that nothing is missed in between. Binding
+                        // is idempotent, so an unchanged binding must not then be unbound.
+                        let unchanged = old.exchange == binding.exchange
+                            && old.routing_key == binding.routing_key
+                            && old.arguments == binding.arguments;
+                        if !unchanged {
+                            channel.queue_bind(
+                                queue.name().as_str(),
+                                &binding.exchange,
+                                &binding.routing_key,
+                                QueueBindOptions::default(),
+                                binding.arguments.clone(),
+                            )
+                            .await?;
+                            channel.queue_unbind(queue.name().as_str(), &old.exchange, &old.routing_key, old.arguments.clone())
+                                .await?;
+                        }
+                        *old = binding.clone();
+                        tx.send(ConnectionUpdate::Rebound(binding)).expect("Internal channel closed");
+                    }
                     None => {
                         log::debug!(
                             "Connection manager incoming channel closed; assume caller exited."
@@ -223,7 +347,7 @@ async fn connection_manager_task(
                         return Ok(());
                     }
                 },
-                r = consumer.next() => match r{
+                r = consumer.next(), if consuming => match r{
                     None => {
                         let _ = tx.send(ConnectionUpdate::Disconnected);
                         continue 'not_connected;
@@ -235,10 +359,23 @@ async fn connection_manager_task(
                         };
 
                         // TODO think about leveraging the content-type here if it's available.
-                        let content_type: Option<String> = msg.properties.content_type().as_ref().map(|c|c.to_string());
+                        let info = DeliveryInfo {
+                            exchange: msg.exchange.to_string(),
+                            routing_key: msg.routing_key.to_string(),
+                            content_type: msg.properties.content_type().as_ref().map(|c|c.to_string()),
+                            correlation_id: msg.properties.correlation_id().as_ref().map(|c|c.to_string()),
+                            reply_to: msg.properties.reply_to().as_ref().map(|r|r.to_string()),
+                            message_id: msg.properties.message_id().as_ref().map(|m|m.to_string()),
+                            size: msg.data.len(),
+                            bindings: bindings
+                                .iter()
+                                .filter(|b| b.matches(msg.exchange.as_str(), msg.routing_key.as_str(), msg.properties.headers().as_ref()))
+                                .map(|b| b.id)
+                                .collect(),
+                        };
                         let update = match String::from_utf8(msg.data) {
-                            Ok(content) => ConnectionUpdate::TextDelivery { headers, content, content_type },
-                            Err(_) => ConnectionUpdate::BinaryDelivery{ headers, content_type},
+                            Ok(content) => ConnectionUpdate::TextDelivery { headers, content, info },
+                            Err(_) => ConnectionUpdate::BinaryDelivery{ headers, info },
                         };
                         tx.send(update).expect("Internal channel closed");
                     }